@@ -0,0 +1,147 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::SystemTime;
+
+use sha2::{Digest, Sha256};
+
+use crate::config::{self, Settings};
+use super::deezer;
+
+/// disk budget for list thumbnails, they're a few kB each
+const THUMB_CACHE_BYTES: u64 = 50 * 1024 * 1024;
+/// longest side of a list thumbnail, rows show them at 32px
+const THUMB_SIZE: u32 = 128;
+/// thumbnail downloads at once, a long download list asks for all of them together
+const THUMB_FETCHES: usize = 6;
+
+/// cover art by url, from the disk cache when we've seen it before.
+/// `cfg.cover_max` > 0 scales it down to fit and re-encodes as jpeg
+pub async fn get(url: &str, cfg: &Settings) -> Option<Vec<u8>> {
+    cached(&config::cover_cache_dir(), url, cfg.cover_max, cfg.cover_cache_mb as u64 * 1024 * 1024).await
+}
+
+/// small cover for list rows, cached apart so they don't push out full covers
+pub async fn thumb(url: &str) -> Option<Vec<u8>> {
+    static FETCHES: tokio::sync::Semaphore = tokio::sync::Semaphore::const_new(THUMB_FETCHES);
+    let _permit = FETCHES.acquire().await.ok()?;
+    cached(&config::thumb_cache_dir(), url, THUMB_SIZE, THUMB_CACHE_BYTES).await
+}
+
+async fn cached(dir: &Path, url: &str, max: u32, limit: u64) -> Option<Vec<u8>> {
+    if url.is_empty() { return None; }
+    let path = dir.join(key(url, max));
+
+    // every track of an album asks at once, only the first one downloads
+    let lock = inflight(&path);
+    let _held = lock.lock().await;
+
+    if let Ok(data) = fs::read(&path) {
+        touch(&path);
+        return Some(data);
+    }
+    let data = deezer::fetch_cover(url).await.ok()?;
+    // tags and folder art want jpeg or png, youtube serves webp
+    let plain = matches!(mime(&data), "image/jpeg" | "image/png");
+    let data = if max == 0 && plain {
+        data
+    } else {
+        tokio::task::spawn_blocking(move || shrink(&data, max).unwrap_or(data)).await.ok()?
+    };
+    if fs::create_dir_all(dir).is_ok() && fs::write(&path, &data).is_ok() {
+        evict(dir, limit);
+    }
+    Some(data)
+}
+
+/// `cover.jpg` and `folder.jpg` next to the album's tracks, existing ones are kept.
+/// png covers are re-encoded so the names stay the same whatever the source
+pub fn write_folder_art(dir: &Path, data: &[u8]) {
+    let missing: Vec<PathBuf> = ["cover.jpg", "folder.jpg"].iter()
+        .map(|name| dir.join(name))
+        .filter(|p| !p.exists())
+        .collect();
+    if missing.is_empty() { return; }
+    let jpeg;
+    let data = if mime(data) == "image/jpeg" {
+        data
+    } else {
+        let Some(j) = shrink(data, 0) else { return };
+        jpeg = j;
+        &jpeg
+    };
+    for path in missing {
+        let _ = fs::write(path, data);
+    }
+}
+
+/// image type from the magic bytes, whatever the url or server claims
+pub fn mime(data: &[u8]) -> &'static str {
+    if data.starts_with(b"\x89PNG") {
+        "image/png"
+    } else if data.starts_with(b"RIFF") && data.get(8..12) == Some(b"WEBP".as_slice()) {
+        "image/webp"
+    } else if data.starts_with(b"GIF8") {
+        "image/gif"
+    } else {
+        "image/jpeg"
+    }
+}
+
+fn key(url: &str, max: u32) -> String {
+    let hash: String = Sha256::digest(url.as_bytes()).iter().map(|b| format!("{b:02x}")).collect();
+    if max > 0 { format!("{hash}_{max}") } else { hash }
+}
+
+fn inflight(path: &Path) -> Arc<tokio::sync::Mutex<()>> {
+    static LOCKS: OnceLock<Mutex<HashMap<PathBuf, Arc<tokio::sync::Mutex<()>>>>> = OnceLock::new();
+    let Ok(mut locks) = LOCKS.get_or_init(Default::default).lock() else {
+        return Arc::default();
+    };
+    // drop locks nobody is waiting on
+    locks.retain(|_, l| Arc::strong_count(l) > 1);
+    locks.entry(path.to_path_buf()).or_default().clone()
+}
+
+/// re-encode as jpeg, scaled to fit `max` unless it's 0
+fn shrink(data: &[u8], max: u32) -> Option<Vec<u8>> {
+    let img = image::load_from_memory(data).ok()?;
+    let img = if max > 0 && (img.width() > max || img.height() > max) {
+        img.resize(max, max, image::imageops::FilterType::Lanczos3)
+    } else {
+        img
+    };
+    let mut out = Vec::new();
+    image::codecs::jpeg::JpegEncoder::new_with_quality(&mut out, 90)
+        .encode_image(&img.to_rgb8())
+        .ok()?;
+    Some(out)
+}
+
+/// the mtime doubles as last use for eviction
+fn touch(path: &Path) {
+    if let Ok(f) = fs::File::options().append(true).open(path) {
+        let _ = f.set_modified(SystemTime::now());
+    }
+}
+
+/// drop least recently used covers until `dir` fits in `limit` bytes
+fn evict(dir: &Path, limit: u64) {
+    let Ok(entries) = fs::read_dir(dir) else { return };
+    let mut files: Vec<(SystemTime, u64, PathBuf)> = entries
+        .flatten()
+        .filter_map(|e| {
+            let m = e.metadata().ok()?;
+            Some((m.modified().ok()?, m.len(), e.path()))
+        })
+        .collect();
+    let mut total: u64 = files.iter().map(|f| f.1).sum();
+    files.sort();
+    for (_, len, path) in files {
+        if total <= limit { break; }
+        if fs::remove_file(path).is_ok() {
+            total -= len;
+        }
+    }
+}
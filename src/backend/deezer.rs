@@ -1,7 +1,10 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+
 use crate::models::{Album, Artist, Track};
-use crate::models::album::DzAlbumRes;
-use crate::models::artist::DzArtistRes;
-use crate::models::track::DzTrackRes;
+use crate::models::album::{DzAlbum, DzAlbumRes};
+use crate::models::artist::{DzArtist, DzArtistRes};
+use crate::models::track::{DzTrack, DzTrackRes};
 
 const API: &str = "https://api.deezer.com";
 const LIMIT: u32 = 25;
@@ -38,10 +41,94 @@ pub async fn album_tracks(album: &Album) -> Result<Vec<Track>, String> {
     Ok(res.data.iter().map(|dt| {
         let mut t = Track::from_dz(dt, &album.title, &album.cover_url);
         t.is_album_track = true;
+        if t.thumb_url.is_empty() { t.thumb_url = album.thumb_url.clone(); }
+        t.dz_album_id = album.id;
+        t.album_artist = album.artist.clone();
+        t.track_total = Some(album.nb_tracks).filter(|n| *n > 0);
+        t.release_date = album.release_date.clone();
         t
     }).collect())
 }
 
+pub async fn track(id: u64) -> Result<Track, String> {
+    let dt: DzTrack = get(&format!("{API}/track/{id}")).await?;
+    // unknown ids answer with an error object rather than a 404
+    if dt.id == 0 { return Err(String::from("track not found")); }
+    Ok(Track::from_dz(&dt, "", ""))
+}
+
+pub async fn album_by_id(id: u64) -> Result<Album, String> {
+    album(id).await.map(|da| Album::from_dz(&da)).ok_or_else(|| String::from("album not found"))
+}
+
+pub async fn artist(id: u64) -> Result<Artist, String> {
+    let da: DzArtist = get(&format!("{API}/artist/{id}")).await?;
+    if da.id == 0 { return Err(String::from("artist not found")); }
+    Ok(Artist::from_dz(&da))
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct DzPlaylist {
+    #[serde(default)]
+    id: u64,
+    #[serde(default)]
+    title: String,
+}
+
+/// a public playlist's name and every track on it
+pub async fn playlist(id: u64) -> Result<(String, Vec<Track>), String> {
+    let pl: DzPlaylist = get(&format!("{API}/playlist/{id}")).await?;
+    if pl.id == 0 { return Err(String::from("playlist not found")); }
+    let mut all = Vec::new();
+    let mut url = format!("{API}/playlist/{id}/tracks?limit=100");
+    loop {
+        let res: DzTrackRes = get(&url).await?;
+        all.extend(res.data.iter().map(|dt| Track::from_dz(dt, "", "")));
+        match res.next {
+            Some(next) => url = next,
+            None => break,
+        }
+    }
+    Ok((pl.title, all))
+}
+
+/// full track and album lookups for the tags search results don't carry.
+/// tracks without a deezer id (spotify) are matched by isrc. errors leave
+/// the track as it was, missing tags aren't worth failing a download over
+pub async fn enrich(mut track: Track) -> Track {
+    let url = match (track.dz_id, track.isrc.as_str()) {
+        (0, "") => None,
+        (0, isrc) => Some(format!("{API}/track/isrc:{}", enc(isrc))),
+        (id, _) => Some(format!("{API}/track/{id}")),
+    };
+    if let Some(url) = url {
+        if let Ok(dt) = get::<DzTrack>(&url).await {
+            // isrc lookups answer with an error object rather than a 404
+            if dt.id != 0 { track.merge_dz(&dt); }
+        }
+    }
+    if track.dz_album_id != 0 {
+        if let Some(da) = album(track.dz_album_id).await {
+            track.merge_dz_album(&da);
+        }
+    }
+    track
+}
+
+/// album details, cached since every track of an album asks for the same one
+async fn album(id: u64) -> Option<Arc<DzAlbum>> {
+    static CACHE: OnceLock<Mutex<HashMap<u64, Arc<DzAlbum>>>> = OnceLock::new();
+    let cache = CACHE.get_or_init(Default::default);
+    if let Some(da) = cache.lock().ok()?.get(&id) {
+        return Some(da.clone());
+    }
+    let da: DzAlbum = get(&format!("{API}/album/{id}")).await.ok()?;
+    if da.id == 0 { return None; }
+    let da = Arc::new(da);
+    cache.lock().ok()?.insert(id, da.clone());
+    Some(da)
+}
+
 pub async fn fetch_cover(url: &str) -> Result<Vec<u8>, String> {
     if url.is_empty() { return Err(String::from("no cover url")); }
     Ok(reqwest::get(url).await
@@ -0,0 +1,135 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::SystemTime;
+use tokio::process::Command;
+
+use crate::config::Settings;
+use crate::models::{AudioFormat, Track};
+use super::matcher::normalize;
+use super::{history, ytdlp};
+
+/// why a track counts as already downloaded
+#[derive(Debug, Clone)]
+pub enum Dupe {
+    Path(PathBuf),
+    Tags(PathBuf),
+    History(PathBuf),
+}
+
+impl Dupe {
+    pub fn path(&self) -> &Path {
+        match self {
+            Self::Path(p) | Self::Tags(p) | Self::History(p) => p,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Self::Path(p) => format!("file exists: {}", p.display()),
+            Self::Tags(p) => format!("tags match: {}", p.display()),
+            Self::History(p) => format!("in download history: {}", p.display()),
+        }
+    }
+}
+
+/// normalized tags of a file on disk
+#[derive(Debug, Clone)]
+struct FileTags {
+    artist: String,
+    /// the first of several credited artists
+    primary: String,
+    title: String,
+}
+
+/// tags per file, reused while the file's mtime is unchanged
+type TagCache = HashMap<PathBuf, (SystemTime, Option<FileTags>)>;
+
+/// one tag scan at a time, so parallel jobs looking at the same folder probe
+/// each file once and then share the cache
+static SCAN: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+/// where a tag like "A, B", "A & B" or "A feat. B" ends its first artist
+const ARTIST_SEPARATORS: &[&str] = &[",", "&", ";", "/", " x ", " feat.", " feat ", " ft.", " featuring ", "(feat", "(ft"];
+
+fn tag_cache() -> &'static Mutex<TagCache> {
+    static CACHE: OnceLock<Mutex<TagCache>> = OnceLock::new();
+    CACHE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+pub async fn find(track: &Track, base: &Path, cfg: &Settings) -> Option<Dupe> {
+    for fmt in AudioFormat::ALL {
+        let path = ytdlp::target_path(base, track, cfg, fmt);
+        if path.exists() {
+            return Some(Dupe::Path(path));
+        }
+    }
+
+    // the artist tag may list featured artists after the primary one
+    let (artist, title) = (normalize(&track.artist), normalize(&track.title));
+    let _scan = SCAN.lock().await;
+    for file in audio_files(&ytdlp::target_dir(base, track, cfg)) {
+        if let Some(tags) = file_tags(&file).await {
+            if tags.title == title && (tags.artist == artist || tags.primary == artist) {
+                return Some(Dupe::Tags(file));
+            }
+        }
+    }
+
+    history::find(track).map(Dupe::History)
+}
+
+fn audio_files(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(dir) else { return Vec::new() };
+    entries
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| {
+            let ext = p.extension().and_then(|e| e.to_str()).unwrap_or("");
+            AudioFormat::ALL.iter().any(|f| f.ext().eq_ignore_ascii_case(ext))
+        })
+        .collect()
+}
+
+async fn file_tags(path: &Path) -> Option<FileTags> {
+    let mtime = std::fs::metadata(path).and_then(|m| m.modified()).ok()?;
+    if let Some((t, tags)) = tag_cache().lock().ok()?.get(path) {
+        if *t == mtime { return tags.clone(); }
+    }
+    let tags = probe_tags(path).await;
+    if let Ok(mut cache) = tag_cache().lock() {
+        cache.insert(path.to_path_buf(), (mtime, tags.clone()));
+    }
+    tags
+}
+
+/// artist and title via ffprobe, ogg keeps them on the stream rather than the container
+async fn probe_tags(path: &Path) -> Option<FileTags> {
+    let out = Command::new("ffprobe")
+        .args(["-v", "quiet", "-of", "json", "-show_entries", "format_tags:stream_tags"])
+        .arg(path)
+        .kill_on_drop(true)
+        .output()
+        .await
+        .ok()?;
+    let json: serde_json::Value = serde_json::from_slice(&out.stdout).ok()?;
+    let mut tags = vec![&json["format"]["tags"]];
+    if let Some(streams) = json["streams"].as_array() {
+        tags.extend(streams.iter().map(|s| &s["tags"]));
+    }
+    let get = |key: &str| {
+        tags.iter()
+            .filter_map(|t| t.as_object())
+            .flat_map(|t| t.iter())
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .and_then(|(_, v)| v.as_str())
+    };
+    let artist = get("artist")?;
+    Some(FileTags { artist: normalize(artist), primary: primary_artist(artist), title: normalize(get("title")?) })
+}
+
+fn primary_artist(tag: &str) -> String {
+    let lower = tag.to_lowercase();
+    let end = ARTIST_SEPARATORS.iter().filter_map(|sep| lower.find(sep)).min().unwrap_or(lower.len());
+    normalize(&lower[..end])
+}
@@ -0,0 +1,169 @@
+use std::collections::HashSet;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use tokio::sync::{watch, Semaphore, SemaphorePermit};
+
+/// hands out download slots and holds them back while the queue is paused.
+/// also tracks running child processes so a pause can suspend them
+pub struct Gate {
+    sem: Semaphore,
+    slots: Mutex<Slots>,
+    rate_limit: AtomicU32,
+    paused: watch::Sender<bool>,
+    suspended: AtomicBool,
+    procs: Mutex<HashSet<u32>>,
+}
+
+/// configured slot count, and permits still owed after shrinking
+/// while every slot was taken
+struct Slots {
+    total: usize,
+    debt: usize,
+}
+
+/// keeps a child's process group registered with the gate. dropping it
+/// before `finish` (cancel, error) kills the whole group, not just the leader
+pub struct ProcGuard<'a> {
+    gate: &'a Gate,
+    pid: u32,
+    finished: bool,
+}
+
+impl ProcGuard<'_> {
+    pub fn finish(&mut self) {
+        self.finished = true;
+    }
+}
+
+impl Drop for ProcGuard<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut procs) = self.gate.procs.lock() {
+            procs.remove(&self.pid);
+        }
+        if !self.finished {
+            signal(self.pid, "KILL");
+        }
+    }
+}
+
+impl Gate {
+    pub fn new(slots: usize, rate_limit: u32) -> Self {
+        Self {
+            sem: Semaphore::new(slots),
+            slots: Mutex::new(Slots { total: slots, debt: 0 }),
+            rate_limit: AtomicU32::new(rate_limit),
+            paused: watch::channel(false).0,
+            suspended: AtomicBool::new(false),
+            procs: Mutex::new(HashSet::new()),
+        }
+    }
+
+    pub async fn acquire(&self) -> Option<SemaphorePermit<'_>> {
+        loop {
+            self.wait_unpaused().await;
+            let permit = self.sem.acquire().await.ok()?;
+            if let Ok(mut slots) = self.slots.lock() {
+                if slots.debt > 0 {
+                    slots.debt -= 1;
+                    permit.forget();
+                    continue;
+                }
+            }
+            // paused while we were waiting for the slot
+            if !self.is_paused() {
+                return Some(permit);
+            }
+        }
+    }
+
+    pub async fn wait_unpaused(&self) {
+        let mut rx = self.paused.subscribe();
+        let _ = rx.wait_for(|p| !*p).await;
+    }
+
+    /// resize on the fly, running downloads keep their slot
+    pub fn set_slots(&self, n: usize) {
+        let n = n.max(1);
+        let Ok(mut slots) = self.slots.lock() else { return };
+        if n > slots.total {
+            let mut extra = n - slots.total;
+            let paid = extra.min(slots.debt);
+            slots.debt -= paid;
+            extra -= paid;
+            self.sem.add_permits(extra);
+        } else if n < slots.total {
+            let fewer = slots.total - n;
+            let forgotten = self.sem.forget_permits(fewer);
+            slots.debt += fewer - forgotten;
+        }
+        slots.total = n;
+    }
+
+    pub fn slots(&self) -> usize {
+        self.slots.lock().map(|s| s.total).unwrap_or(1)
+    }
+
+    /// total bandwidth cap in KiB/s, 0 for none. read by each job as it starts
+    pub fn set_rate_limit(&self, kib: u32) {
+        self.rate_limit.store(kib, Ordering::SeqCst);
+    }
+
+    pub fn rate_limit(&self) -> u32 {
+        self.rate_limit.load(Ordering::SeqCst)
+    }
+
+    pub fn is_paused(&self) -> bool {
+        *self.paused.borrow()
+    }
+
+    /// `suspend` also stops the processes that are already running
+    pub fn pause(&self, suspend: bool) {
+        self.paused.send_replace(true);
+        if suspend {
+            self.suspended.store(true, Ordering::SeqCst);
+            for pid in self.pids() {
+                signal(pid, "STOP");
+            }
+        }
+    }
+
+    pub fn resume(&self) {
+        if self.suspended.swap(false, Ordering::SeqCst) {
+            for pid in self.pids() {
+                signal(pid, "CONT");
+            }
+        }
+        self.paused.send_replace(false);
+    }
+
+    /// register a child spawned as a process group leader. one that starts
+    /// during a suspending pause is stopped right away
+    pub fn track(&self, pid: Option<u32>) -> Option<ProcGuard<'_>> {
+        let pid = pid?;
+        if let Ok(mut procs) = self.procs.lock() {
+            procs.insert(pid);
+        }
+        if self.suspended.load(Ordering::SeqCst) {
+            signal(pid, "STOP");
+        }
+        Some(ProcGuard { gate: self, pid, finished: false })
+    }
+
+    fn pids(&self) -> Vec<u32> {
+        self.procs.lock().map(|p| p.iter().copied().collect()).unwrap_or_default()
+    }
+}
+
+/// signal the whole process group so the ffmpeg yt-dlp spawns follows along
+#[cfg(unix)]
+fn signal(pid: u32, sig: &str) {
+    let _ = std::process::Command::new("kill")
+        .arg(format!("-{sig}"))
+        .arg("--")
+        .arg(format!("-{pid}"))
+        .stderr(std::process::Stdio::null())
+        .status();
+}
+
+#[cfg(not(unix))]
+fn signal(_pid: u32, _sig: &str) {}
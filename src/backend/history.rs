@@ -0,0 +1,51 @@
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use serde::{Deserialize, Serialize};
+
+use crate::config;
+use crate::models::Track;
+use super::matcher::normalize;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub artist: String,
+    pub title: String,
+    pub album: String,
+    pub path: PathBuf,
+}
+
+fn cache() -> &'static Mutex<Vec<Entry>> {
+    static CACHE: OnceLock<Mutex<Vec<Entry>>> = OnceLock::new();
+    CACHE.get_or_init(|| {
+        let list = std::fs::read_to_string(config::history_path())
+            .ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default();
+        Mutex::new(list)
+    })
+}
+
+pub fn record(track: &Track, path: &Path) {
+    let Ok(mut list) = cache().lock() else { return };
+    list.retain(|e| !same(e, track));
+    list.push(Entry {
+        artist: track.artist.clone(),
+        title: track.title.clone(),
+        album: track.album.clone(),
+        path: path.to_path_buf(),
+    });
+    let file = config::history_path();
+    let _ = std::fs::create_dir_all(file.parent().unwrap_or(&file));
+    let _ = std::fs::write(&file, serde_json::to_string(&*list).unwrap_or_default());
+}
+
+/// the recorded file for `track`, skipping ones that were moved or deleted since
+pub fn find(track: &Track) -> Option<PathBuf> {
+    cache().lock().ok()?.iter().find(|e| same(e, track) && e.path.exists()).map(|e| e.path.clone())
+}
+
+fn same(e: &Entry, track: &Track) -> bool {
+    normalize(&e.artist) == normalize(&track.artist)
+        && normalize(&e.title) == normalize(&track.title)
+        && normalize(&e.album) == normalize(&track.album)
+}
@@ -0,0 +1,193 @@
+use serde::Deserialize;
+use tokio::process::Command;
+
+use crate::config;
+use crate::models::Track;
+
+/// looks like something to hand to yt-dlp rather than a search query
+pub fn is_url(s: &str) -> bool {
+    let s = s.trim();
+    (s.starts_with("https://") || s.starts_with("http://")) && !s.contains(char::is_whitespace)
+}
+
+/// what a share link points at
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Track,
+    Album,
+    Artist,
+    Playlist,
+}
+
+impl Kind {
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "track" => Some(Self::Track),
+            "album" => Some(Self::Album),
+            "artist" => Some(Self::Artist),
+            "playlist" => Some(Self::Playlist),
+            _ => None,
+        }
+    }
+}
+
+/// a deezer or spotify page we can open through their apis instead of yt-dlp
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Share {
+    Deezer(Kind, u64),
+    /// deezer.page.link and link.deezer.com, only the redirect knows what's behind them
+    DeezerShort(String),
+    Spotify(Kind, String),
+}
+
+/// `open.spotify.com/album/ID`, `spotify:track:ID`, `deezer.com/en/track/123` and deezer short links
+pub fn share(s: &str) -> Option<Share> {
+    let s = s.trim();
+    if let Some(rest) = s.strip_prefix("spotify:") {
+        let (kind, id) = rest.split_once(':')?;
+        return Some(Share::Spotify(Kind::parse(kind)?, id.to_string()));
+    }
+    if !is_url(s) { return None; }
+    let rest = s.split_once("://")?.1;
+    let (host, path) = rest.split_once('/').unwrap_or((rest, ""));
+    let host = host.strip_prefix("www.").unwrap_or(host).to_ascii_lowercase();
+    let path = path.split(['?', '#']).next().unwrap_or("");
+    // locale and embed prefixes sit in front of the kind, skip to the first known one
+    let mut parts = path.split('/').filter(|p| !p.is_empty()).skip_while(|p| Kind::parse(p).is_none());
+    let kind = parts.next().and_then(Kind::parse);
+    let id = parts.next().unwrap_or("");
+
+    match host.as_str() {
+        "deezer.page.link" | "link.deezer.com" => Some(Share::DeezerShort(s.to_string())),
+        "deezer.com" => Some(Share::Deezer(kind?, id.parse().ok()?)),
+        "open.spotify.com" | "play.spotify.com" if !id.is_empty() => Some(Share::Spotify(kind?, id.to_string())),
+        _ => None,
+    }
+}
+
+/// follow a deezer short link to the page it stands for
+pub async fn resolve_short(url: &str) -> Result<Share, String> {
+    let res = reqwest::get(url.trim()).await.map_err(|e| format!("req: {e}"))?;
+    match share(res.url().as_str()) {
+        Some(Share::DeezerShort(_)) | None => Err(String::from("unrecognized deezer link")),
+        Some(link) => Ok(link),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Info {
+    #[serde(default, rename = "_type")]
+    kind: Option<String>,
+    #[serde(default)]
+    title: String,
+    /// set by youtube music, bandcamp and soundcloud for actual songs
+    #[serde(default)]
+    track: Option<String>,
+    #[serde(default)]
+    artist: Option<String>,
+    #[serde(default)]
+    creator: Option<String>,
+    #[serde(default)]
+    channel: Option<String>,
+    #[serde(default)]
+    uploader: Option<String>,
+    #[serde(default)]
+    album: Option<String>,
+    #[serde(default)]
+    track_number: Option<u32>,
+    #[serde(default)]
+    duration: Option<f64>,
+    #[serde(default)]
+    thumbnail: Option<String>,
+    #[serde(default)]
+    thumbnails: Vec<Thumb>,
+    #[serde(default)]
+    webpage_url: Option<String>,
+    #[serde(default)]
+    url: Option<String>,
+    #[serde(default)]
+    entries: Vec<Info>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Thumb {
+    #[serde(default)]
+    url: String,
+    #[serde(default)]
+    width: Option<u32>,
+}
+
+/// narrowest thumbnail still sharp in a list row
+const THUMB_MIN_WIDTH: u32 = 120;
+
+/// tracks behind a url yt-dlp understands, one per entry for playlists
+pub async fn fetch(url: &str) -> Result<Vec<Track>, String> {
+    let out = Command::new(config::ytdlp_bin())
+        .args(["-J", "--flat-playlist", "--no-warnings"])
+        .arg(url.trim())
+        .kill_on_drop(true)
+        .output()
+        .await
+        .map_err(|e| format!("spawn: {e}"))?;
+    if !out.status.success() {
+        let err = String::from_utf8_lossy(&out.stderr);
+        return Err(err.lines().last().unwrap_or("yt-dlp failed").to_string());
+    }
+    let info: Info = serde_json::from_slice(&out.stdout).map_err(|e| format!("parse: {e}"))?;
+
+    if info.kind.as_deref() == Some("playlist") {
+        let name = info.title.clone();
+        let tracks: Vec<Track> = info.entries.iter()
+            .filter_map(|e| {
+                let mut t = to_track(e)?;
+                t.playlist = name.clone();
+                Some(t)
+            })
+            .collect();
+        if tracks.is_empty() {
+            return Err(String::from("empty playlist"));
+        }
+        return Ok(tracks);
+    }
+    to_track(&info).map(|t| vec![t]).ok_or_else(|| String::from("no downloadable media"))
+}
+
+fn to_track(i: &Info) -> Option<Track> {
+    let source = i.webpage_url.clone().or_else(|| i.url.clone()).filter(|u| is_url(u))?;
+    let artist = [&i.artist, &i.creator, &i.channel, &i.uploader]
+        .into_iter()
+        .flatten()
+        .find(|s| !s.is_empty())
+        .map(|s| s.trim_end_matches(" - Topic").to_string())
+        .unwrap_or_default();
+    let (cover, thumb) = pick_thumbs(i);
+    Some(Track {
+        title: i.track.clone().filter(|t| !t.is_empty()).unwrap_or_else(|| i.title.clone()),
+        artists: if artist.is_empty() { Vec::new() } else { vec![artist.clone()] },
+        artist,
+        album: i.album.clone().unwrap_or_default(),
+        duration: i.duration.unwrap_or(0.0),
+        track_pos: i.track_number,
+        thumb_url: thumb,
+        cover_url: cover,
+        source_url: source,
+        ..Track::default()
+    })
+}
+
+/// full cover and list thumbnail. flat playlist entries often only carry a list of
+/// thumbnails, worst first. the best ones tend to be webp, covers::get re-encodes those
+fn pick_thumbs(i: &Info) -> (String, String) {
+    let cover = i.thumbnail.clone()
+        .or_else(|| i.thumbnails.last().map(|t| t.url.clone()))
+        .unwrap_or_default();
+    let jpegs = i.thumbnails.iter().filter(|t| {
+        let path = t.url.split(['?', '#']).next().unwrap_or("").to_ascii_lowercase();
+        path.ends_with(".jpg") || path.ends_with(".jpeg")
+    });
+    let thumb = jpegs
+        .filter(|t| t.width.is_some_and(|w| w >= THUMB_MIN_WIDTH))
+        .min_by_key(|t| t.width)
+        .map_or_else(|| cover.clone(), |t| t.url.clone());
+    (cover, thumb)
+}
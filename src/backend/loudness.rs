@@ -0,0 +1,177 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+use serde::{Deserialize, Serialize};
+use tokio::process::Command;
+
+use crate::models::AudioFormat;
+use super::tags;
+
+/// replaygain 2.0 reference loudness
+pub const REFERENCE: f64 = -18.0;
+/// headroom left when the gain is applied to the audio itself
+const CEILING: f64 = -1.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum GainMode {
+    Off,
+    /// replaygain tags, album gain once every track of an album is done
+    #[default]
+    Tags,
+    /// re-encode at the track gain, for players that ignore replaygain
+    Apply,
+}
+
+impl GainMode {
+    pub const ALL: [GainMode; 3] = [Self::Off, Self::Tags, Self::Apply];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Off => "Off",
+            Self::Tags => "ReplayGain tags",
+            Self::Apply => "Apply to audio",
+        }
+    }
+
+    pub fn index(self) -> u32 {
+        Self::ALL.iter().position(|m| *m == self).unwrap_or(0) as u32
+    }
+
+    pub fn from_index(i: u32) -> Self {
+        Self::ALL.get(i as usize).copied().unwrap_or_default()
+    }
+}
+
+/// ebu r128 integrated loudness and true peak of one file
+#[derive(Debug, Clone, Copy)]
+pub struct Scan {
+    pub lufs: f64,
+    pub peak_db: f64,
+}
+
+impl Scan {
+    pub fn gain(&self) -> f64 {
+        REFERENCE - self.lufs
+    }
+
+    pub fn peak(&self) -> f64 {
+        10f64.powf(self.peak_db / 20.0)
+    }
+}
+
+/// scans of finished files, so album gain doesn't decode every track again
+fn cache() -> &'static Mutex<HashMap<PathBuf, Scan>> {
+    static CACHE: OnceLock<Mutex<HashMap<PathBuf, Scan>>> = OnceLock::new();
+    CACHE.get_or_init(Default::default)
+}
+
+pub fn remember(path: &Path, scan: Scan) {
+    if let Ok(mut c) = cache().lock() {
+        c.insert(path.to_path_buf(), scan);
+    }
+}
+
+pub async fn scan(file: &Path) -> Result<Scan, String> {
+    let out = Command::new("ffmpeg")
+        .args(["-hide_banner", "-nostats", "-i"])
+        .arg(file)
+        .args(["-map", "0:a", "-filter:a", "ebur128=peak=true", "-f", "null", "-"])
+        .kill_on_drop(true)
+        .output()
+        .await
+        .map_err(|e| format!("ffmpeg: {e}"))?;
+    if !out.status.success() {
+        return Err(format!("ebur128 failed: {}", String::from_utf8_lossy(&out.stderr)));
+    }
+    parse_summary(&String::from_utf8_lossy(&out.stderr))
+        .ok_or_else(|| String::from("no loudness summary in ffmpeg output"))
+}
+
+/// the summary comes last, so the last `I:` and `Peak:` lines are its values
+fn parse_summary(log: &str) -> Option<Scan> {
+    let value = |key: &str| {
+        log.lines()
+            .rev()
+            .find_map(|l| l.trim().strip_prefix(key))
+            .and_then(|rest| rest.split_whitespace().next())
+            .and_then(|v| v.parse::<f64>().ok())
+    };
+    let lufs = value("I:")?;
+    // -inf for digital silence
+    let peak_db = value("Peak:").filter(|p| p.is_finite()).unwrap_or(-70.0);
+    lufs.is_finite().then_some(Scan { lufs, peak_db })
+}
+
+/// track gain written into the audio, kept below the true peak ceiling
+pub async fn apply(file: &Path, fmt: AudioFormat, bitrate: u32, scan: &Scan) -> Result<f64, String> {
+    let gain = scan.gain().min(CEILING - scan.peak_db);
+    let tmp = file.with_extension(format!("gain.{}", fmt.ext()));
+    let out = Command::new("ffmpeg")
+        .args(["-y", "-hide_banner", "-i"])
+        .arg(file)
+        .args(["-map", "0:a", "-filter:a", &format!("volume={gain:.2}dB")])
+        .args(encoder_args(fmt, bitrate))
+        .arg(&tmp)
+        .kill_on_drop(true)
+        .output()
+        .await
+        .map_err(|e| format!("ffmpeg: {e}"))?;
+    if !out.status.success() {
+        let _ = fs::remove_file(&tmp);
+        return Err(format!("gain apply failed: {}", String::from_utf8_lossy(&out.stderr)));
+    }
+    fs::rename(&tmp, file).map_err(|e| format!("rename: {e}"))?;
+    Ok(gain)
+}
+
+fn encoder_args(fmt: AudioFormat, kbps: u32) -> Vec<String> {
+    let (codec, best) = match fmt {
+        AudioFormat::Mp3 => ("libmp3lame", ["-q:a", "0"]),
+        AudioFormat::Flac => ("flac", ["-compression_level", "8"]),
+        AudioFormat::Opus => ("libopus", ["-b:a", "160k"]),
+        AudioFormat::M4a => ("aac", ["-b:a", "256k"]),
+        AudioFormat::Vorbis => ("libvorbis", ["-q:a", "6"]),
+    };
+    let mut args = vec![String::from("-c:a"), codec.to_string()];
+    if kbps > 0 && !fmt.lossless() {
+        args.extend([String::from("-b:a"), format!("{kbps}k")]);
+    } else {
+        args.extend(best.map(String::from));
+    }
+    args
+}
+
+/// album gain over finished tracks with their durations, as the energy
+/// weighted mean of the track loudness. writes the album tags to every file
+pub async fn tag_album(files: Vec<(PathBuf, f64)>) -> Result<String, String> {
+    let mut scans = Vec::new();
+    for (path, secs) in &files {
+        let cached = cache().lock().ok().and_then(|c| c.get(path).copied());
+        let s = match cached {
+            Some(s) => s,
+            None => {
+                let s = scan(path).await?;
+                remember(path, s);
+                s
+            }
+        };
+        scans.push((s, secs.max(1.0)));
+    }
+    let total: f64 = scans.iter().map(|(_, d)| d).sum();
+    let energy: f64 = scans.iter().map(|(s, d)| d * 10f64.powf(s.lufs / 10.0)).sum::<f64>() / total;
+    let album = Scan {
+        lufs: 10.0 * energy.log10(),
+        peak_db: scans.iter().map(|(s, _)| s.peak_db).fold(f64::MIN, f64::max),
+    };
+
+    let paths: Vec<PathBuf> = files.into_iter().map(|(p, _)| p).collect();
+    let n = paths.len();
+    tokio::task::spawn_blocking(move || {
+        paths.iter().try_for_each(|p| tags::set_gain(p, None, Some(&album)))
+    })
+    .await
+    .map_err(|e| format!("tag task: {e}"))??;
+    Ok(format!("album gain {:+.2} dB over {n} tracks", album.gain()))
+}
@@ -0,0 +1,104 @@
+use std::time::Duration;
+use serde::Deserialize;
+
+use crate::models::Track;
+
+pub const DEFAULT_URL: &str = "https://lrclib.net";
+
+/// a slow server holds up the download and its slot, lyrics aren't worth that
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Lyrics {
+    #[serde(default)]
+    pub duration: Option<f64>,
+    #[serde(default)]
+    pub instrumental: bool,
+    #[serde(default)]
+    pub plain_lyrics: Option<String>,
+    #[serde(default)]
+    pub synced_lyrics: Option<String>,
+}
+
+impl Lyrics {
+    /// plain text, stripped from the synced version when that's all there is
+    pub fn plain(&self) -> Option<String> {
+        if let Some(p) = self.plain_lyrics.as_ref().filter(|p| !p.trim().is_empty()) {
+            return Some(p.clone());
+        }
+        let synced = self.synced()?;
+        Some(synced.lines().map(strip_stamp).collect::<Vec<_>>().join("\n").trim().to_string())
+    }
+
+    pub fn synced(&self) -> Option<&str> {
+        self.synced_lyrics.as_deref().filter(|s| !s.trim().is_empty())
+    }
+}
+
+/// look `track` up on an lrclib compatible api at `base`. the exact lookup
+/// needs album and duration, without them or on a miss it falls back to search
+pub async fn fetch(base: &str, track: &Track) -> Result<Lyrics, String> {
+    let base = base.trim_end_matches('/');
+    let client = reqwest::Client::builder()
+        .user_agent(concat!("music-downloader/", env!("CARGO_PKG_VERSION")))
+        .connect_timeout(TIMEOUT)
+        .timeout(TIMEOUT)
+        .build()
+        .map_err(|e| format!("client: {e}"))?;
+
+    if !track.album.is_empty() && track.duration > 0.0 {
+        let res = client.get(format!("{base}/api/get"))
+            .query(&[
+                ("artist_name", track.artist.as_str()),
+                ("track_name", track.title.as_str()),
+                ("album_name", track.album.as_str()),
+                ("duration", &(track.duration.round() as u64).to_string()),
+            ])
+            .send().await
+            .map_err(req_error)?;
+        if res.status().is_success() {
+            return res.json().await.map_err(parse_error);
+        }
+        if res.status() != reqwest::StatusCode::NOT_FOUND {
+            return Err(format!("lyrics api: {}", res.status()));
+        }
+    }
+
+    let list: Vec<Lyrics> = client.get(format!("{base}/api/search"))
+        .query(&[("artist_name", track.artist.as_str()), ("track_name", track.title.as_str())])
+        .send().await
+        .map_err(req_error)?
+        .error_for_status()
+        .map_err(|e| format!("lyrics api: {e}"))?
+        .json().await
+        .map_err(parse_error)?;
+
+    // closest duration wins, synced breaks ties. anything 5s off is another version
+    let off = |l: &Lyrics| match (track.duration > 0.0, l.duration) {
+        (true, Some(d)) => (d - track.duration).abs(),
+        _ => 0.0,
+    };
+    list.into_iter()
+        .filter(|l| off(l) <= 5.0)
+        .min_by(|a, b| off(a).total_cmp(&off(b)).then(b.synced().is_some().cmp(&a.synced().is_some())))
+        .ok_or_else(|| String::from("no lyrics found"))
+}
+
+fn req_error(e: reqwest::Error) -> String {
+    if e.is_timeout() { String::from("no lyrics, the server timed out") } else { format!("req: {e}") }
+}
+
+/// the body is read under the same timeout
+fn parse_error(e: reqwest::Error) -> String {
+    if e.is_timeout() { req_error(e) } else { format!("parse: {e}") }
+}
+
+/// "[01:23.45] line" -> "line"
+fn strip_stamp(line: &str) -> &str {
+    let mut rest = line;
+    while let Some(end) = rest.strip_prefix('[').and_then(|r| r.find(']')) {
+        rest = &rest[end + 2..];
+    }
+    rest.trim_start()
+}
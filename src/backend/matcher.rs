@@ -0,0 +1,155 @@
+use serde::Deserialize;
+use tokio::process::Command;
+
+use crate::config;
+use crate::models::Track;
+
+/// words that usually mean the upload isn't the studio recording
+const PENALTY: &[&str] = &[
+    "live", "cover", "sped up", "speed up", "slowed", "reverb", "nightcore", "karaoke",
+    "instrumental", "remix", "8d", "acoustic", "extended", "bass boosted", "reaction",
+];
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Candidate {
+    #[serde(default)]
+    pub id: String,
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub channel: Option<String>,
+    #[serde(default)]
+    pub uploader: Option<String>,
+    #[serde(default)]
+    pub duration: Option<f64>,
+    #[serde(default)]
+    pub webpage_url: String,
+}
+
+impl Candidate {
+    pub fn channel(&self) -> &str {
+        self.channel.as_deref().or(self.uploader.as_deref()).unwrap_or("")
+    }
+
+    pub fn url(&self) -> String {
+        if self.webpage_url.is_empty() {
+            format!("https://www.youtube.com/watch?v={}", self.id)
+        } else {
+            self.webpage_url.clone()
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        let dur = self.duration.map_or(String::from("?"), |d| format!("{}:{:02}", d as u64 / 60, d as u64 % 60));
+        format!("{} [{}] {dur}", self.title, self.channel())
+    }
+}
+
+/// search prefix and candidate rank to use on a given attempt (0-based):
+/// the best two youtube results, then youtube music, then soundcloud,
+/// then further down the youtube list
+pub fn plan(attempt: u32) -> (&'static str, usize) {
+    match attempt {
+        0 => ("ytsearch", 0),
+        1 => ("ytsearch", 1),
+        2 => ("ytmsearch", 0),
+        3 => ("scsearch", 0),
+        n => ("ytsearch", n as usize - 2),
+    }
+}
+
+/// fetch metadata for the first `n` results of `prefix:{query}`
+pub async fn candidates(prefix: &str, query: &str, n: u32) -> Result<Vec<Candidate>, String> {
+    let out = Command::new(config::ytdlp_bin())
+        .args(["--dump-json", "--skip-download", "--no-warnings", "--ignore-errors"])
+        .arg(format!("{prefix}{}:{query}", n.max(1)))
+        .kill_on_drop(true)
+        .output()
+        .await
+        .map_err(|e| format!("spawn: {e}"))?;
+
+    let list: Vec<Candidate> = String::from_utf8_lossy(&out.stdout)
+        .lines()
+        .filter_map(|l| serde_json::from_str(l).ok())
+        .collect();
+
+    if list.is_empty() {
+        return Err(format!("no candidates\n{}", String::from_utf8_lossy(&out.stderr)));
+    }
+    Ok(list)
+}
+
+/// candidates sorted best first with their scores
+pub fn rank(track: &Track, list: Vec<Candidate>) -> Vec<(Candidate, f64)> {
+    let mut scored: Vec<(Candidate, f64)> = list.into_iter()
+        .map(|c| { let s = score(track, &c); (c, s) })
+        .collect();
+    scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+    scored
+}
+
+pub fn score(track: &Track, c: &Candidate) -> f64 {
+    let title = normalize(&c.title);
+    let channel = normalize(c.channel());
+    let want_title = normalize(&track.title);
+    let want_artist = normalize(&track.artist);
+    let mut score = 0.0;
+
+    // duration is the strongest signal, full marks within 3s, nothing past 30s
+    if let (true, Some(d)) = (track.duration > 0.0, c.duration) {
+        let diff = (d - track.duration).abs();
+        score += 40.0 * (1.0 - ((diff - 3.0).max(0.0) / 27.0)).max(0.0);
+        if diff > 60.0 { score -= 20.0; }
+    }
+
+    let stripped = strip_words(&title, &want_artist);
+    score += 30.0 * similarity(&want_title, &stripped);
+
+    if !want_artist.is_empty() && (title.contains(&want_artist) || channel.contains(&want_artist)) {
+        score += 15.0;
+    }
+
+    if c.channel().ends_with(" - Topic") {
+        score += 15.0;
+    } else if channel.contains("vevo") || channel.contains("official") || title.contains("official audio") {
+        score += 5.0;
+    }
+
+    for word in PENALTY {
+        if has_word(&title, word) && !has_word(&want_title, word) {
+            score -= 25.0;
+        }
+    }
+
+    score
+}
+
+pub fn normalize(s: &str) -> String {
+    s.to_lowercase()
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn has_word(haystack: &str, word: &str) -> bool {
+    format!(" {haystack} ").contains(&format!(" {word} "))
+}
+
+fn strip_words(s: &str, words: &str) -> String {
+    let drop: Vec<&str> = words.split(' ').collect();
+    s.split(' ').filter(|w| !drop.contains(w)).collect::<Vec<_>>().join(" ")
+}
+
+/// share of the wanted words found, lightly penalized by extra words in the candidate
+fn similarity(want: &str, got: &str) -> f64 {
+    let a: std::collections::HashSet<&str> = want.split(' ').filter(|w| !w.is_empty()).collect();
+    let b: std::collections::HashSet<&str> = got.split(' ').filter(|w| !w.is_empty()).collect();
+    if a.is_empty() || b.is_empty() { return 0.0; }
+    let common = a.intersection(&b).count() as f64;
+    let recall = common / a.len() as f64;
+    let jaccard = common / a.union(&b).count() as f64;
+    0.7 * recall + 0.3 * jaccard
+}
@@ -1,5 +1,17 @@
+pub mod covers;
 pub mod deezer;
+pub mod dupes;
 pub mod ffmpeg;
+pub mod gate;
+pub mod history;
+pub mod links;
+pub mod loudness;
+pub mod lyrics;
+pub mod matcher;
+pub mod queue;
+pub mod sanitize;
 pub mod spotify;
+pub mod tags;
+pub mod template;
 pub mod ytdlp;
 pub mod ytdlp_setup;
@@ -0,0 +1,75 @@
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+
+use crate::config::{self, Settings};
+use crate::models::{DlStatus, Track};
+use super::ytdlp;
+
+/// one unfinished download as written to the queue file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueueEntry {
+    pub track: Track,
+    pub status: DlStatus,
+    pub dir: PathBuf,
+}
+
+/// how long a burst of finished tracks settles before the queue hits disk
+const SAVE_DELAY: Duration = Duration::from_secs(1);
+
+/// newest queue not written yet
+static PENDING: Mutex<Option<Vec<QueueEntry>>> = Mutex::new(None);
+/// one writer at a time, it takes the newest queue once it holds this
+static WRITING: Mutex<()> = Mutex::new(());
+
+/// save off the ui thread. a burst of calls ends up as a single write of the last queue
+pub fn save_later(entries: Vec<QueueEntry>) {
+    let Ok(mut pending) = PENDING.lock() else { return };
+    let idle = pending.is_none();
+    *pending = Some(entries);
+    if !idle { return; }
+    std::thread::spawn(|| {
+        std::thread::sleep(SAVE_DELAY);
+        flush();
+    });
+}
+
+/// write a queue still waiting in `save_later` now, before the app exits
+pub fn flush() {
+    let Ok(_writing) = WRITING.lock() else { return };
+    let entries = PENDING.lock().ok().and_then(|mut p| p.take());
+    if let Some(entries) = entries {
+        save(&entries);
+    }
+}
+
+pub fn save(entries: &[QueueEntry]) {
+    let path = config::queue_path();
+    if entries.is_empty() {
+        let _ = std::fs::remove_file(&path);
+        return;
+    }
+    let _ = std::fs::create_dir_all(path.parent().unwrap_or(&path));
+    let tmp = path.with_extension("json.tmp");
+    // write then rename so a crash mid-write keeps the previous queue
+    if std::fs::write(&tmp, serde_json::to_string(entries).unwrap_or_default()).is_ok() {
+        let _ = std::fs::rename(&tmp, &path);
+    }
+}
+
+pub fn load() -> Vec<QueueEntry> {
+    std::fs::read_to_string(config::queue_path())
+        .ok()
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_default()
+}
+
+/// drop temp files a killed session left next to where `track` would land
+pub fn clean_partials(dir: &Path, track: &Track, cfg: &Settings) {
+    ytdlp::remove_partials(dir, ytdlp::PARTIAL_PREFIX);
+    let target = ytdlp::target_dir(dir, track, cfg);
+    if target != dir {
+        ytdlp::remove_partials(&target, ytdlp::PARTIAL_PREFIX);
+    }
+}
@@ -0,0 +1,100 @@
+use serde::{Deserialize, Serialize};
+use unicode_normalization::UnicodeNormalization;
+
+/// which filesystem's naming rules file and folder names must follow
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NameProfile {
+    Posix,
+    Windows,
+    /// windows rules plus a tighter length budget for car stereos and sd cards
+    Fat32,
+}
+
+impl Default for NameProfile {
+    fn default() -> Self {
+        if cfg!(windows) { Self::Windows } else { Self::Posix }
+    }
+}
+
+const RESERVED: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL",
+    "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9",
+    "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+impl NameProfile {
+    pub const ALL: [NameProfile; 3] = [Self::Posix, Self::Windows, Self::Fat32];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Posix => "Linux / macOS",
+            Self::Windows => "Windows (NTFS)",
+            Self::Fat32 => "FAT32 (car stereo, SD card)",
+        }
+    }
+
+    pub fn index(self) -> u32 {
+        Self::ALL.iter().position(|p| *p == self).unwrap_or(0) as u32
+    }
+
+    pub fn from_index(i: u32) -> Self {
+        Self::ALL.get(i as usize).copied().unwrap_or_default()
+    }
+
+    /// max bytes per name, utf-8 bytes never undercount utf-16 units so this is safe for both
+    fn max_bytes(self) -> usize {
+        match self {
+            Self::Posix | Self::Windows => 255,
+            // keeps full paths on old head units under their 255 char limit
+            Self::Fat32 => 120,
+        }
+    }
+}
+
+/// one path component, `ext` is kept intact when the name gets truncated
+pub fn component(name: &str, ext: Option<&str>, profile: NameProfile, ascii: bool) -> String {
+    let name: String = name.nfc().collect();
+    let name = if ascii { deunicode::deunicode(&name) } else { name };
+    let strict = profile != NameProfile::Posix;
+
+    let mut out = String::with_capacity(name.len());
+    for c in name.chars() {
+        match c {
+            '/' => out.push('-'),
+            c if c.is_control() => {}
+            '\\' | '|' if strict => out.push('-'),
+            ':' if strict => out.push_str(" -"),
+            '"' if strict => out.push('\''),
+            '<' if strict => out.push('('),
+            '>' if strict => out.push(')'),
+            '?' | '*' if strict => {}
+            c => out.push(c),
+        }
+    }
+    let mut out = out.split_whitespace().collect::<Vec<_>>().join(" ");
+
+    // a leading dot hides the file, and collides with our temp prefix
+    out = out.trim_start_matches('.').trim_start().to_string();
+    if strict {
+        out = out.trim_end_matches(['.', ' ']).to_string();
+        let stem = out.split('.').next().unwrap_or("").trim_end();
+        if RESERVED.iter().any(|r| r.eq_ignore_ascii_case(stem)) {
+            out.insert(0, '_');
+        }
+    }
+    if out.is_empty() {
+        out.push('_');
+    }
+
+    let suffix = ext.map_or(String::new(), |e| format!(".{e}"));
+    let budget = profile.max_bytes().saturating_sub(suffix.len());
+    if out.len() > budget {
+        let mut cut = budget;
+        while !out.is_char_boundary(cut) { cut -= 1; }
+        out.truncate(cut);
+        if strict {
+            out = out.trim_end_matches(['.', ' ']).to_string();
+        }
+    }
+    out + &suffix
+}
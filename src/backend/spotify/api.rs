@@ -1,24 +1,114 @@
-use crate::models::Track;
+use std::time::Duration;
+
+use reqwest::StatusCode;
+use reqwest::header::RETRY_AFTER;
+
+use crate::models::{Album, Artist, Track};
+use super::auth;
+use super::tokens::save_tokens;
 use super::types::*;
 
 const API: &str = "https://api.spotify.com/v1";
+/// refresh this long before the token runs out rather than racing it
+const EXPIRY_MARGIN: u64 = 60;
+/// rate limited retries per request before giving up
+const MAX_RETRIES: u32 = 5;
+/// longest Retry-After we sit out, spotify sometimes asks for hours. longer ones fail the request
+const MAX_WAIT: u64 = 60;
+
+/// newest tokens we know of. the ui hands us its copy, which is stale after the first refresh
+static LIVE: tokio::sync::Mutex<Option<Tokens>> = tokio::sync::Mutex::const_new(None);
+
+/// client credentials token shared by every public lookup
+static PUBLIC: tokio::sync::Mutex<Option<Tokens>> = tokio::sync::Mutex::const_new(None);
+
+/// a usable access token, refreshed when it's about to expire or when `rejected` is the current one
+async fn access_token(tokens: &Tokens, rejected: Option<&str>) -> Result<String, String> {
+    if tokens.refresh_token.is_empty() {
+        return public_token(rejected).await;
+    }
+    // held across the refresh so parallel requests don't each burn the refresh token
+    let mut live = LIVE.lock().await;
+    let cur = match live.take() {
+        Some(t) if t.expires_at >= tokens.expires_at => t,
+        _ => tokens.clone(),
+    };
+    let stale = rejected == Some(cur.access_token.as_str()) || cur.expires_at <= auth::now() + EXPIRY_MARGIN;
+    let cur = if stale {
+        let fresh = auth::refresh(&cur).await?;
+        save_tokens(&fresh);
+        fresh
+    } else {
+        cur
+    };
+    let token = cur.access_token.clone();
+    *live = Some(cur);
+    Ok(token)
+}
 
-async fn authed_get<T: serde::de::DeserializeOwned>(token: &str, url: &str) -> Result<T, String> {
-    reqwest::Client::new()
-        .get(url)
-        .header("Authorization", format!("Bearer {token}"))
-        .send()
-        .await
-        .map_err(|e| format!("req: {e}"))?
-        .json()
-        .await
-        .map_err(|e| format!("parse: {e}"))
+/// client credentials tokens can't be refreshed, a new one is requested instead. nothing to persist
+async fn public_token(rejected: Option<&str>) -> Result<String, String> {
+    let mut public = PUBLIC.lock().await;
+    let stale = public.as_ref().is_none_or(|t| {
+        rejected == Some(t.access_token.as_str()) || t.expires_at <= auth::now() + EXPIRY_MARGIN
+    });
+    if stale {
+        *public = Some(auth::client_token().await?);
+    }
+    Ok(public.as_ref().map_or(String::new(), |t| t.access_token.clone()))
+}
+
+async fn authed_get<T: serde::de::DeserializeOwned>(tokens: &Tokens, url: &str) -> Result<T, String> {
+    let client = reqwest::Client::new();
+    let mut token = access_token(tokens, None).await?;
+    let mut refreshed = false;
+    let mut retries = 0;
+
+    loop {
+        let res = client
+            .get(url)
+            .bearer_auth(&token)
+            .send()
+            .await
+            .map_err(|e| format!("req: {e}"))?;
+
+        match res.status() {
+            StatusCode::UNAUTHORIZED if !refreshed => {
+                refreshed = true;
+                token = access_token(tokens, Some(&token)).await?;
+            }
+            StatusCode::TOO_MANY_REQUESTS if retries < MAX_RETRIES => {
+                retries += 1;
+                let wait = res
+                    .headers()
+                    .get(RETRY_AFTER)
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(|v| v.trim().parse::<u64>().ok())
+                    .unwrap_or(1);
+                if wait > MAX_WAIT {
+                    return Err(format!("rate limited, retry in {wait}s"));
+                }
+                tokio::time::sleep(Duration::from_secs(wait.max(1))).await;
+            }
+            s if s.is_success() => return res.json().await.map_err(|e| format!("parse: {e}")),
+            s => return Err(api_error(s, &res.text().await.unwrap_or_default())),
+        }
+    }
+}
+
+/// spotify's own message when the body has one, the status line otherwise
+fn api_error(status: StatusCode, body: &str) -> String {
+    match serde_json::from_str::<ApiError>(body) {
+        Ok(e) if !e.error.message.is_empty() => format!("{}: {}", status.as_u16(), e.error.message),
+        _ => status.to_string(),
+    }
 }
 
 fn track_from_raw(raw: &RawTrack) -> Track {
     Track {
         title: raw.name.clone(),
         artist: raw.artists.first().map_or(String::new(), |a| a.name.clone()),
+        artists: raw.artists.iter().map(|a| a.name.clone()).filter(|n| !n.is_empty()).collect(),
         album: raw.album.as_ref().map_or(String::new(), |a| a.name.clone()),
         duration: raw.duration_ms / 1000.0,
         track_pos: raw.track_number,
@@ -27,16 +117,68 @@ fn track_from_raw(raw: &RawTrack) -> Track {
             .as_ref()
             .and_then(|a| a.images.first())
             .map_or(String::new(), |i| i.url.clone()),
-        is_album_track: false,
+        // images come widest first
+        thumb_url: raw
+            .album
+            .as_ref()
+            .and_then(|a| a.images.last())
+            .map_or(String::new(), |i| i.url.clone()),
+        album_artist: raw
+            .album
+            .as_ref()
+            .and_then(|a| a.artists.first())
+            .map_or(String::new(), |a| a.name.clone()),
+        disc: raw.disc_number,
+        track_total: raw.album.as_ref().and_then(|a| a.total_tracks),
+        isrc: raw.external_ids.isrc.clone(),
+        release_date: raw.album.as_ref().map_or(String::new(), |a| a.release_date.clone()),
+        explicit: raw.explicit,
+        ..Track::default()
+    }
+}
+
+fn album_from_raw(raw: &RawAlbum) -> Album {
+    Album {
+        title: raw.name.clone(),
+        artist: raw.artists.first().map_or(String::new(), |a| a.name.clone()),
+        cover_url: raw.images.first().map_or(String::new(), |i| i.url.clone()),
+        thumb_url: raw.images.last().map_or(String::new(), |i| i.url.clone()),
+        nb_tracks: raw.total_tracks.unwrap_or(0),
+        release_date: raw.release_date.clone(),
+        sp_id: raw.id.clone(),
+        ..Album::default()
     }
 }
 
+fn artist_from_raw(raw: &RawArtist) -> Artist {
+    Artist {
+        name: raw.name.clone(),
+        sp_id: raw.id.clone(),
+        ..Artist::default()
+    }
+}
+
+/// every item behind a paged listing
+async fn all_pages<T: serde::de::DeserializeOwned>(tokens: &Tokens, url: String) -> Result<Vec<T>, String> {
+    let mut all = Vec::new();
+    let mut url = url;
+    loop {
+        let page: Page<T> = authed_get(tokens, &url).await?;
+        all.extend(page.items);
+        match page.next {
+            Some(next) => url = next,
+            None => break,
+        }
+    }
+    Ok(all)
+}
+
 pub async fn playlists(tokens: &Tokens) -> Result<Vec<Playlist>, String> {
     let mut all = Vec::new();
     let mut url = format!("{API}/me/playlists?limit=50");
 
     loop {
-        let res: PlaylistsRes = authed_get(&tokens.access_token, &url).await?;
+        let res: PlaylistsRes = authed_get(tokens, &url).await?;
         all.extend(res.items.iter().map(|p| Playlist {
             id: p.id.clone(),
             name: p.name.clone(),
@@ -56,7 +198,7 @@ pub async fn playlist_tracks(tokens: &Tokens, id: &str) -> Result<Vec<Track>, St
     let mut url = format!("{API}/playlists/{id}/tracks?limit=50");
 
     loop {
-        let res: PlaylistTracksRes = authed_get(&tokens.access_token, &url).await?;
+        let res: PlaylistTracksRes = authed_get(tokens, &url).await?;
         all.extend(res.items.iter().filter_map(|i| i.track.as_ref()).map(track_from_raw));
         match res.next {
             Some(next) => url = next,
@@ -72,7 +214,7 @@ pub async fn liked_tracks(tokens: &Tokens) -> Result<Vec<Track>, String> {
     let mut url = format!("{API}/me/tracks?limit=50");
 
     loop {
-        let res: SavedTracksRes = authed_get(&tokens.access_token, &url).await?;
+        let res: SavedTracksRes = authed_get(tokens, &url).await?;
         all.extend(res.items.iter().map(|i| track_from_raw(&i.track)));
         match res.next {
             Some(next) => url = next,
@@ -82,3 +224,93 @@ pub async fn liked_tracks(tokens: &Tokens) -> Result<Vec<Track>, String> {
 
     Ok(all)
 }
+
+pub async fn track(tokens: &Tokens, id: &str) -> Result<Track, String> {
+    let raw: RawTrack = authed_get(tokens, &format!("{API}/tracks/{id}")).await?;
+    Ok(track_from_raw(&raw))
+}
+
+/// a playlist's name and tracks, for shared links to playlists that aren't ours
+pub async fn playlist(tokens: &Tokens, id: &str) -> Result<(String, Vec<Track>), String> {
+    let info: PlaylistInfo = authed_get(tokens, &format!("{API}/playlists/{id}?fields=name")).await?;
+    Ok((info.name, playlist_tracks(tokens, id).await?))
+}
+
+pub async fn album_tracks(tokens: &Tokens, id: &str) -> Result<Vec<Track>, String> {
+    let res: FullAlbum = authed_get(tokens, &format!("{API}/albums/{id}")).await?;
+    let mut items = res.tracks.items;
+    if let Some(next) = res.tracks.next {
+        items.extend(all_pages::<RawTrack>(tokens, next).await?);
+    }
+
+    Ok(items.into_iter().map(|mut raw| {
+        raw.album = Some(res.album.clone());
+        let mut t = track_from_raw(&raw);
+        t.is_album_track = true;
+        t
+    }).collect())
+}
+
+/// catalog search, `kinds` is spotify's comma separated type list
+pub async fn search(tokens: &Tokens, q: &str, kinds: &str) -> Result<Found, String> {
+    let url = reqwest::Url::parse_with_params(&format!("{API}/search"), [("q", q), ("type", kinds), ("limit", "20")])
+        .map_err(|e| format!("url: {e}"))?;
+    let res: SearchRes = authed_get(tokens, url.as_str()).await?;
+
+    Ok(Found {
+        tracks: res.tracks.map_or_else(Vec::new, |p| p.items.iter().map(track_from_raw).collect()),
+        albums: res.albums.map_or_else(Vec::new, |p| p.items.iter().map(album_from_raw).collect()),
+        artists: res.artists.map_or_else(Vec::new, |p| p.items.iter().map(artist_from_raw).collect()),
+        playlists: res.playlists.map_or_else(Vec::new, |p| p.items.iter().flatten().map(|p| Playlist {
+            id: p.id.clone(),
+            name: p.name.clone(),
+            nb_tracks: p.tracks.total,
+        }).collect()),
+    })
+}
+
+pub async fn saved_albums(tokens: &Tokens) -> Result<Vec<Album>, String> {
+    let saved: Vec<SavedAlbum> = all_pages(tokens, format!("{API}/me/albums?limit=50")).await?;
+    Ok(saved.iter().map(|s| album_from_raw(&s.album)).collect())
+}
+
+pub async fn artist_albums(tokens: &Tokens, id: &str) -> Result<Vec<Album>, String> {
+    let url = format!("{API}/artists/{id}/albums?include_groups=album,single&limit=50");
+    let albums: Vec<RawAlbum> = all_pages(tokens, url).await?;
+    Ok(albums.iter().map(album_from_raw).collect())
+}
+
+pub async fn followed_artists(tokens: &Tokens) -> Result<Vec<Artist>, String> {
+    let mut all = Vec::new();
+    let mut url = format!("{API}/me/following?type=artist&limit=50");
+
+    // the artists page is wrapped in an object, so no all_pages
+    loop {
+        let res: FollowingRes = authed_get(tokens, &url).await?;
+        all.extend(res.artists.items.iter().map(artist_from_raw));
+        match res.artists.next {
+            Some(next) => url = next,
+            None => break,
+        }
+    }
+
+    Ok(all)
+}
+
+pub async fn top_tracks(tokens: &Tokens, term: Term) -> Result<Vec<Track>, String> {
+    let url = format!("{API}/me/top/tracks?time_range={}&limit=50", term.param());
+    let tracks: Vec<RawTrack> = all_pages(tokens, url).await?;
+    Ok(tracks.iter().map(track_from_raw).collect())
+}
+
+/// the last 50 plays, spotify keeps no more. repeats are listed once
+pub async fn recently_played(tokens: &Tokens) -> Result<Vec<Track>, String> {
+    let res: Page<PlayHistory> = authed_get(tokens, &format!("{API}/me/player/recently-played?limit=50")).await?;
+    let mut out: Vec<Track> = Vec::new();
+    for t in res.items.iter().map(|h| track_from_raw(&h.track)) {
+        if !out.iter().any(|o| o.title == t.title && o.artist == t.artist) {
+            out.push(t);
+        }
+    }
+    Ok(out)
+}
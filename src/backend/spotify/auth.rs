@@ -2,24 +2,42 @@ use sha2::{Sha256, Digest};
 use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
 use rand::Rng;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::TcpListener;
-use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use std::io::ErrorKind;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use super::types::{Tokens, TokenRes, ProfileRes};
+use super::tokens::{load_client_id, load_client_secret};
+use super::types::{AuthError, Tokens, TokenRes, ProfileRes};
 
 const AUTH_URL: &str = "https://accounts.spotify.com/authorize";
 const TOKEN_URL: &str = "https://accounts.spotify.com/api/token";
 const API: &str = "https://api.spotify.com/v1";
-const PORT: u16 = 18492;
-const REDIRECT: &str = "http://127.0.0.1:18492/callback";
-const SCOPES: &str = "playlist-read-private playlist-read-collaborative user-library-read";
+pub const DEFAULT_PORT: u16 = 18492;
+const SCOPES: &str = "playlist-read-private playlist-read-collaborative user-library-read \
+                      user-follow-read user-top-read user-read-recently-played";
+/// how long we wait for the user to finish logging in
+const AUTH_TIMEOUT: Duration = Duration::from_secs(300);
+/// browsers open spare connections they never send on, don't let one hold up the callback
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_REQUEST: usize = 8192;
+
+/// has to match one of the redirect uris registered for the spotify app exactly
+pub fn redirect_uri(port: u16) -> String {
+    format!("http://127.0.0.1:{port}/callback")
+}
+
+/// scopes this version asks for that `tokens` were never granted. a login
+/// from an older version has to be redone before the new sections work
+pub fn missing_scopes(tokens: &Tokens) -> Vec<&'static str> {
+    SCOPES.split_whitespace().filter(|s| !tokens.has_scope(s)).collect()
+}
 
-fn gen_verifier() -> String {
+fn random_string(len: usize) -> String {
     let chars: Vec<char> = "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789-._~"
         .chars()
         .collect();
     let mut rng = rand::thread_rng();
-    (0..64).map(|_| chars[rng.gen_range(0..chars.len())]).collect()
+    (0..len).map(|_| chars[rng.gen_range(0..chars.len())]).collect()
 }
 
 fn pkce_challenge(verifier: &str) -> String {
@@ -35,64 +53,121 @@ fn open_browser(url: &str) {
     let _ = std::process::Command::new("cmd").args(["/C", "start", url]).spawn();
 }
 
-async fn listen_callback() -> Result<String, String> {
-    let listener = TcpListener::bind(format!("127.0.0.1:{PORT}"))
-        .await
-        .map_err(|e| format!("bind: {e}"))?;
-
-    let (mut stream, _) = tokio::time::timeout(Duration::from_secs(120), listener.accept())
-        .await
-        .map_err(|_| String::from("auth timeout"))?
-        .map_err(|e| format!("accept: {e}"))?;
+async fn bind(port: u16) -> Result<TcpListener, String> {
+    TcpListener::bind(("127.0.0.1", port)).await.map_err(|e| match e.kind() {
+        ErrorKind::AddrInUse => format!(
+            "port {port} is in use, pick another callback port in settings and add {} to your spotify app",
+            redirect_uri(port)
+        ),
+        _ => format!("bind {port}: {e}"),
+    })
+}
 
-    let mut buf = vec![0u8; 4096];
-    let n = stream.read(&mut buf).await.map_err(|e| format!("read: {e}"))?;
-    let req = String::from_utf8_lossy(&buf[..n]);
+/// path of a GET request, None for anything else or a connection that never sends
+async fn read_path(stream: &mut TcpStream) -> Option<String> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 1024];
+    while !buf.windows(4).any(|w| w == b"\r\n\r\n") && buf.len() < MAX_REQUEST {
+        let n = tokio::time::timeout(READ_TIMEOUT, stream.read(&mut chunk)).await.ok()?.ok()?;
+        if n == 0 { break; }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+    let req = String::from_utf8_lossy(&buf);
+    let mut line = req.lines().next()?.split_whitespace();
+    if line.next()? != "GET" { return None; }
+    line.next().map(str::to_string)
+}
 
-    let code = req
-        .lines()
-        .next()
-        .and_then(|line| line.split_whitespace().nth(1))
-        .and_then(|path| path.split('?').nth(1))
-        .and_then(|qs| qs.split('&').find(|p| p.starts_with("code=")))
-        .map(|p| p.trim_start_matches("code=").to_string())
-        .ok_or_else(|| String::from("no code in callback"))?;
+async fn respond(stream: &mut TcpStream, status: &str, title: &str, msg: &str) {
+    let body = format!(
+        "<!doctype html><html><head><meta charset=\"utf-8\"><title>{title}</title></head>\
+         <body style=\"font-family:sans-serif;text-align:center;margin-top:4em\">\
+         <h3>{title}</h3><p>{msg}</p></body></html>"
+    );
+    let res = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: text/html; charset=utf-8\r\n\
+         Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    let _ = stream.write_all(res.as_bytes()).await;
+    let _ = stream.shutdown().await;
+}
 
-    let html = "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\n\r\n\
-                <html><body><h3>connected, you can close this tab</h3></body></html>";
-    let _ = stream.write_all(html.as_bytes()).await;
+/// code, state and error are all plain ascii, no percent decoding needed
+fn param<'a>(qs: &'a str, key: &str) -> Option<&'a str> {
+    qs.split('&').find_map(|p| p.strip_prefix(key)?.strip_prefix('='))
+}
 
-    Ok(code)
+/// serve the loopback redirect until spotify comes back with our `state`.
+/// favicons, prefetches and stale tabs get an error page and we keep waiting
+async fn listen_callback(listener: TcpListener, state: &str) -> Result<String, String> {
+    let deadline = tokio::time::Instant::now() + AUTH_TIMEOUT;
+
+    loop {
+        let (mut stream, _) = tokio::time::timeout_at(deadline, listener.accept())
+            .await
+            .map_err(|_| String::from("auth timeout"))?
+            .map_err(|e| format!("accept: {e}"))?;
+
+        let Some(path) = read_path(&mut stream).await else { continue };
+        let Some(qs) = path.strip_prefix("/callback?") else {
+            respond(&mut stream, "404 Not Found", "Not found", "").await;
+            continue;
+        };
+        // not from the login we started, could be an old tab or another site poking the port
+        if param(qs, "state") != Some(state) {
+            respond(&mut stream, "400 Bad Request", "Login not recognized",
+                "start connecting again from the app's settings").await;
+            continue;
+        }
+
+        if let Some(err) = param(qs, "error") {
+            if err == "access_denied" {
+                respond(&mut stream, "200 OK", "Login cancelled", "you can close this tab").await;
+                return Err(String::from("login cancelled"));
+            }
+            respond(&mut stream, "200 OK", "Login failed", "you can close this tab and try again").await;
+            let err: String = err.chars().filter(|c| c.is_ascii_alphanumeric() || *c == '_').collect();
+            return Err(format!("spotify: {err}"));
+        }
+        return match param(qs, "code").filter(|c| !c.is_empty()) {
+            Some(code) => {
+                respond(&mut stream, "200 OK", "Connected", "you can close this tab").await;
+                Ok(code.to_string())
+            }
+            None => {
+                respond(&mut stream, "400 Bad Request", "Login failed", "spotify sent no code, try again").await;
+                Err(String::from("no code in callback"))
+            }
+        };
+    }
 }
 
-pub async fn authorize(client_id: &str) -> Result<Tokens, String> {
-    let verifier = gen_verifier();
+/// pkce login through the browser. `port` is where the loopback redirect listens
+pub async fn authorize(client_id: &str, port: u16) -> Result<Tokens, String> {
+    let verifier = random_string(64);
     let challenge = pkce_challenge(&verifier);
+    let state = random_string(32);
+    let redirect = redirect_uri(port);
+
+    // bind first so a busy port fails before the browser opens
+    let listener = bind(port).await?;
 
     let auth_url = format!(
-        "{AUTH_URL}?client_id={client_id}&response_type=code&redirect_uri={REDIRECT}\
-         &code_challenge={challenge}&code_challenge_method=S256&scope={SCOPES}"
+        "{AUTH_URL}?client_id={client_id}&response_type=code&redirect_uri={redirect}\
+         &code_challenge={challenge}&code_challenge_method=S256&scope={SCOPES}&state={state}"
     );
 
     open_browser(&auth_url);
-    let code = listen_callback().await?;
-
-    let client = reqwest::Client::new();
-    let token_res: TokenRes = client
-        .post(TOKEN_URL)
-        .form(&[
-            ("grant_type", "authorization_code"),
-            ("code", &code),
-            ("redirect_uri", REDIRECT),
-            ("client_id", client_id),
-            ("code_verifier", &verifier),
-        ])
-        .send()
-        .await
-        .map_err(|e| format!("token req: {e}"))?
-        .json()
-        .await
-        .map_err(|e| format!("token parse: {e}"))?;
+    let code = listen_callback(listener, &state).await?;
+
+    let token_res = token_request(None, &[
+        ("grant_type", "authorization_code"),
+        ("code", &code),
+        ("redirect_uri", &redirect),
+        ("client_id", client_id),
+        ("code_verifier", &verifier),
+    ]).await?;
 
     let profile: ProfileRes = reqwest::Client::new()
         .get(format!("{API}/me"))
@@ -108,5 +183,72 @@ pub async fn authorize(client_id: &str) -> Result<Tokens, String> {
         access_token: token_res.access_token,
         refresh_token: token_res.refresh_token.unwrap_or_default(),
         display_name: profile.display_name.unwrap_or_else(|| String::from("spotify user")),
+        expires_at: expires_at(token_res.expires_in),
+        scope: token_res.scope.unwrap_or_else(|| SCOPES.to_string()),
     })
 }
+
+/// trade the refresh token for a new access token, spotify may hand out a new refresh token too
+pub async fn refresh(tokens: &Tokens) -> Result<Tokens, String> {
+    let client_id = load_client_id().ok_or_else(|| String::from("set client id in settings first"))?;
+    let res = token_request(None, &[
+        ("grant_type", "refresh_token"),
+        ("refresh_token", &tokens.refresh_token),
+        ("client_id", &client_id),
+    ]).await?;
+
+    Ok(Tokens {
+        access_token: res.access_token,
+        refresh_token: res.refresh_token.unwrap_or_else(|| tokens.refresh_token.clone()),
+        display_name: tokens.display_name.clone(),
+        expires_at: expires_at(res.expires_in),
+        scope: res.scope.unwrap_or_else(|| tokens.scope.clone()),
+    })
+}
+
+/// app-only token from the client id and secret, it sees the public catalog but no user's library
+pub async fn client_token() -> Result<Tokens, String> {
+    let (Some(id), Some(secret)) = (load_client_id(), load_client_secret()) else {
+        return Err(String::from("set client id and secret in settings first"));
+    };
+    let res = token_request(Some((id.as_str(), secret.as_str())), &[("grant_type", "client_credentials")]).await?;
+
+    Ok(Tokens {
+        access_token: res.access_token,
+        refresh_token: String::new(),
+        display_name: String::new(),
+        expires_at: expires_at(res.expires_in),
+        scope: String::new(),
+    })
+}
+
+/// `basic` is the client id and secret for the confidential flows, pkce sends neither
+async fn token_request(basic: Option<(&str, &str)>, form: &[(&str, &str)]) -> Result<TokenRes, String> {
+    let mut req = reqwest::Client::new().post(TOKEN_URL).form(form);
+    if let Some((id, secret)) = basic {
+        req = req.basic_auth(id, Some(secret));
+    }
+    let res = req.send().await.map_err(|e| format!("token req: {e}"))?;
+
+    let status = res.status();
+    if !status.is_success() {
+        let body = res.text().await.unwrap_or_default();
+        return Err(match serde_json::from_str::<AuthError>(&body) {
+            // revoked access or a refresh token that was already rotated
+            Ok(e) if e.error == "invalid_grant" => String::from("spotify session expired, reconnect"),
+            Ok(e) if !e.error_description.is_empty() => format!("token: {}", e.error_description),
+            Ok(e) if !e.error.is_empty() => format!("token: {}", e.error),
+            _ => format!("token: {status}"),
+        });
+    }
+    res.json().await.map_err(|e| format!("token parse: {e}"))
+}
+
+pub fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+fn expires_at(expires_in: u64) -> u64 {
+    // spotify always says an hour, assume that if it ever doesn't
+    now() + if expires_in == 0 { 3600 } else { expires_in }
+}
@@ -3,7 +3,9 @@ mod auth;
 mod tokens;
 mod types;
 
-pub use auth::authorize;
-pub use api::{playlists, playlist_tracks, liked_tracks};
+pub use auth::{authorize, missing_scopes, redirect_uri, DEFAULT_PORT};
+pub use api::{playlists, playlist_tracks, liked_tracks, playlist, album_tracks, track};
+pub use api::{saved_albums, artist_albums, followed_artists, top_tracks, recently_played, search};
 pub use tokens::{save_tokens, load_tokens, clear_tokens, save_client_id, load_client_id};
-pub use types::{Tokens, Playlist};
+pub use tokens::{save_client_secret, load_client_secret, public_tokens};
+pub use types::{Tokens, Playlist, Section, Term};
@@ -26,3 +26,50 @@ pub fn load_client_id() -> Option<String> {
     let s = s.trim().to_string();
     if s.is_empty() { None } else { Some(s) }
 }
+
+/// only needed for lookups without logging in, empty clears it
+pub fn save_client_secret(secret: &str) {
+    let path = crate::config::data_dir().join("spotify_client_secret");
+    if secret.is_empty() {
+        let _ = std::fs::remove_file(&path);
+        return;
+    }
+    let _ = std::fs::create_dir_all(path.parent().unwrap_or(&path));
+    let _ = write_private(&path, secret);
+}
+
+/// readable by the owner only, also tightens a file left by an older version
+fn write_private(path: &std::path::Path, data: &str) -> std::io::Result<()> {
+    use std::io::Write;
+    let mut opts = std::fs::OpenOptions::new();
+    opts.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        opts.mode(0o600);
+        if path.exists() {
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+        }
+    }
+    opts.open(path)?.write_all(data.as_bytes())
+}
+
+pub fn load_client_secret() -> Option<String> {
+    let s = std::fs::read_to_string(crate::config::data_dir().join("spotify_client_secret")).ok()?;
+    let s = s.trim().to_string();
+    if s.is_empty() { None } else { Some(s) }
+}
+
+/// stand-in tokens for public catalog lookups when nobody is logged in.
+/// the api swaps them for a client credentials token, they can't read any library
+pub fn public_tokens() -> Option<Tokens> {
+    load_client_id()?;
+    load_client_secret()?;
+    Some(Tokens {
+        access_token: String::new(),
+        refresh_token: String::new(),
+        display_name: String::new(),
+        expires_at: 0,
+        scope: String::new(),
+    })
+}
@@ -3,8 +3,25 @@ use serde::{Deserialize, Serialize};
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Tokens {
     pub access_token: String,
+    /// empty for client credentials tokens, which are renewed rather than refreshed
     pub refresh_token: String,
     pub display_name: String,
+    /// unix seconds, 0 for token files saved before we tracked it
+    #[serde(default)]
+    pub expires_at: u64,
+    /// granted scopes, space separated. empty for token files saved before we tracked them
+    #[serde(default)]
+    pub scope: String,
+}
+
+/// what a login granted before the library sections were added
+const LEGACY_SCOPES: &str = "playlist-read-private playlist-read-collaborative user-library-read";
+
+impl Tokens {
+    pub fn has_scope(&self, scope: &str) -> bool {
+        let granted = if self.scope.is_empty() { LEGACY_SCOPES } else { &self.scope };
+        granted.split_whitespace().any(|s| s == scope)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -18,6 +35,32 @@ pub struct Playlist {
 pub struct TokenRes {
     pub access_token: String,
     pub refresh_token: Option<String>,
+    #[serde(default)]
+    pub expires_in: u64,
+    /// left out when it didn't change on a refresh
+    #[serde(default)]
+    pub scope: Option<String>,
+}
+
+/// what the accounts service answers with on a failed token request
+#[derive(Debug, Deserialize)]
+pub struct AuthError {
+    #[serde(default)]
+    pub error: String,
+    #[serde(default)]
+    pub error_description: String,
+}
+
+/// what the web api answers with on any failed request
+#[derive(Debug, Deserialize)]
+pub struct ApiError {
+    pub error: ApiErrorBody,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ApiErrorBody {
+    #[serde(default)]
+    pub message: String,
 }
 
 #[derive(Debug, Deserialize)]
@@ -73,7 +116,7 @@ pub struct SavedTrack {
     pub track: RawTrack,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct RawTrack {
     #[serde(default)]
     pub name: String,
@@ -85,24 +128,159 @@ pub struct RawTrack {
     pub artists: Vec<RawArtist>,
     #[serde(default)]
     pub album: Option<RawAlbum>,
+    #[serde(default)]
+    pub disc_number: Option<u32>,
+    #[serde(default)]
+    pub explicit: bool,
+    #[serde(default)]
+    pub external_ids: ExternalIds,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize, Default)]
+pub struct ExternalIds {
+    #[serde(default)]
+    pub isrc: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
 pub struct RawArtist {
+    #[serde(default)]
+    pub id: String,
     #[serde(default)]
     pub name: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct RawAlbum {
+    #[serde(default)]
+    pub id: String,
     #[serde(default)]
     pub name: String,
     #[serde(default)]
     pub images: Vec<Image>,
+    #[serde(default)]
+    pub artists: Vec<RawArtist>,
+    #[serde(default)]
+    pub release_date: String,
+    #[serde(default)]
+    pub total_tracks: Option<u32>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct Image {
     #[serde(default)]
     pub url: String,
 }
+
+#[derive(Debug, Deserialize)]
+pub struct PlaylistInfo {
+    #[serde(default)]
+    pub name: String,
+}
+
+/// `/albums/{id}`, the tracks in it carry no album of their own
+#[derive(Debug, Deserialize)]
+pub struct FullAlbum {
+    #[serde(flatten)]
+    pub album: RawAlbum,
+    pub tracks: Page<RawTrack>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Page<T> {
+    #[serde(default = "Vec::new")]
+    pub items: Vec<T>,
+    pub next: Option<String>,
+}
+
+/// `/search`, only the types asked for are present
+#[derive(Debug, Deserialize)]
+pub struct SearchRes {
+    pub tracks: Option<Page<RawTrack>>,
+    pub albums: Option<Page<RawAlbum>>,
+    pub artists: Option<Page<RawArtist>>,
+    /// deleted playlists come back as nulls
+    pub playlists: Option<Page<Option<RawPlaylist>>>,
+}
+
+/// catalog search results mapped onto our models
+#[derive(Debug, Default)]
+pub struct Found {
+    pub tracks: Vec<crate::models::Track>,
+    pub albums: Vec<crate::models::Album>,
+    pub artists: Vec<crate::models::Artist>,
+    pub playlists: Vec<Playlist>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SavedAlbum {
+    pub album: RawAlbum,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FollowingRes {
+    pub artists: Page<RawArtist>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PlayHistory {
+    pub track: RawTrack,
+}
+
+/// how far back top tracks look
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Term {
+    Short,
+    Medium,
+    Long,
+}
+
+impl Term {
+    pub const ALL: [Self; 3] = [Self::Short, Self::Medium, Self::Long];
+
+    pub fn param(self) -> &'static str {
+        match self {
+            Self::Short => "short_term",
+            Self::Medium => "medium_term",
+            Self::Long => "long_term",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Short => "last 4 weeks",
+            Self::Medium => "last 6 months",
+            Self::Long => "all time",
+        }
+    }
+}
+
+/// library listings besides playlists and liked songs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Section {
+    SavedAlbums,
+    FollowedArtists,
+    Top(Term),
+    Recent,
+}
+
+impl Section {
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::SavedAlbums => "Saved Albums",
+            Self::FollowedArtists => "Followed Artists",
+            Self::Top(_) => "Top Tracks",
+            Self::Recent => "Recently Played",
+        }
+    }
+
+    /// what the login has to have granted to list it
+    pub fn scope(self) -> &'static str {
+        match self {
+            Self::SavedAlbums => "user-library-read",
+            Self::FollowedArtists => "user-follow-read",
+            Self::Top(_) => "user-top-read",
+            Self::Recent => "user-read-recently-played",
+        }
+    }
+}
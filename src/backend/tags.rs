@@ -0,0 +1,117 @@
+use std::path::Path;
+
+use lofty::config::WriteOptions;
+use lofty::file::{AudioFile, TaggedFileExt};
+use lofty::picture::{MimeType, Picture, PictureType};
+use lofty::tag::{Accessor, ItemKey, ItemValue, Tag, TagItem, TagType};
+
+use crate::config::Settings;
+use crate::models::Track;
+use super::covers;
+use super::loudness::Scan;
+
+/// opus gains are relative to this instead of the replaygain reference
+const R128_REFERENCE: f64 = -23.0;
+
+/// tag `file` in place with whatever the container uses: id3v2.4 for mp3,
+/// vorbis comments for flac/opus/ogg, ilst atoms for m4a.
+/// tags already in the file (yt-dlp, encoder) are replaced
+pub fn write(file: &Path, track: &Track, cfg: &Settings, cover: Option<&[u8]>) -> Result<(), String> {
+    let mut tagged = lofty::read_from_path(file).map_err(|e| format!("tag read: {e}"))?;
+    let mut tag = Tag::new(tagged.primary_tag_type());
+    fill(&mut tag, track, cfg);
+    if let Some(data) = cover {
+        tag.push_picture(front_cover(data));
+    }
+    tagged.insert_tag(tag);
+    tagged.save_to_path(file, WriteOptions::default()).map_err(|e| format!("tag write: {e}"))
+}
+
+fn fill(tag: &mut Tag, track: &Track, cfg: &Settings) {
+    tag.set_title(track.title.clone());
+    // most players only read the first mp4 artist atom, so it always gets the joined form
+    if cfg.multi_artist && tag.tag_type() != TagType::Mp4Ilst {
+        for a in track.all_artists() {
+            tag.push(TagItem::new(ItemKey::TrackArtist, ItemValue::Text(a)));
+        }
+    } else {
+        tag.set_artist(track.artists_joined(&cfg.artist_joiner));
+    }
+    if !track.album.is_empty() {
+        tag.set_album(track.album.clone());
+    }
+    if let Some(pos) = track.track_pos {
+        tag.set_track(pos);
+    }
+    if let Some(total) = track.track_total {
+        tag.set_track_total(total);
+    }
+    if let Some(disc) = track.disc {
+        tag.set_disk(disc);
+    }
+    for g in &track.genres {
+        tag.push(TagItem::new(ItemKey::Genre, ItemValue::Text(g.clone())));
+    }
+    let text = [
+        (ItemKey::AlbumArtist, if track.album_artist.is_empty() { track.artist.clone() } else { track.album_artist.clone() }),
+        (ItemKey::RecordingDate, track.release_date.clone()),
+        (ItemKey::Isrc, track.isrc.clone()),
+        (ItemKey::Label, track.label.clone()),
+        (ItemKey::Bpm, if track.bpm > 0 { track.bpm.to_string() } else { String::new() }),
+        (ItemKey::ParentalAdvisory, if track.explicit { String::from("1") } else { String::new() }),
+    ];
+    for (key, val) in text {
+        if !val.is_empty() {
+            tag.insert_text(key, val);
+        }
+    }
+}
+
+/// add unsynced lyrics to an already tagged file: USLT, LYRICS or ©lyr
+pub fn set_lyrics(file: &Path, text: &str) -> Result<(), String> {
+    let mut tagged = lofty::read_from_path(file).map_err(|e| format!("tag read: {e}"))?;
+    let Some(tag) = tagged.primary_tag_mut() else {
+        return Err(String::from("file has no tag"));
+    };
+    tag.insert_text(ItemKey::Lyrics, text.to_string());
+    tagged.save_to_path(file, WriteOptions::default()).map_err(|e| format!("tag write: {e}"))
+}
+
+/// replaygain tags for the track and/or album. opus takes R128 gains in
+/// q7.8 instead, players are told to ignore REPLAYGAIN_* there
+pub fn set_gain(file: &Path, track: Option<&Scan>, album: Option<&Scan>) -> Result<(), String> {
+    let mut tagged = lofty::read_from_path(file).map_err(|e| format!("tag read: {e}"))?;
+    let Some(tag) = tagged.primary_tag_mut() else {
+        return Err(String::from("file has no tag"));
+    };
+    let opus = file.extension().is_some_and(|e| e.eq_ignore_ascii_case("opus"));
+    let r128 = |s: &Scan| (((R128_REFERENCE - s.lufs) * 256.0).round() as i32).clamp(-32768, 32767).to_string();
+    if let Some(s) = track {
+        if opus {
+            tag.insert_text(ItemKey::Unknown(String::from("R128_TRACK_GAIN")), r128(s));
+        } else {
+            tag.insert_text(ItemKey::ReplayGainTrackGain, format!("{:.2} dB", s.gain()));
+            tag.insert_text(ItemKey::ReplayGainTrackPeak, format!("{:.6}", s.peak()));
+        }
+    }
+    if let Some(s) = album {
+        if opus {
+            tag.insert_text(ItemKey::Unknown(String::from("R128_ALBUM_GAIN")), r128(s));
+        } else {
+            tag.insert_text(ItemKey::ReplayGainAlbumGain, format!("{:.2} dB", s.gain()));
+            tag.insert_text(ItemKey::ReplayGainAlbumPeak, format!("{:.6}", s.peak()));
+        }
+    }
+    tagged.save_to_path(file, WriteOptions::default()).map_err(|e| format!("tag write: {e}"))
+}
+
+/// ogg gets this as a METADATA_BLOCK_PICTURE comment, flac as a picture block
+fn front_cover(data: &[u8]) -> Picture {
+    let mime = match covers::mime(data) {
+        "image/png" => MimeType::Png,
+        "image/jpeg" => MimeType::Jpeg,
+        "image/gif" => MimeType::Gif,
+        other => MimeType::Unknown(other.to_string()),
+    };
+    Picture::new_unchecked(PictureType::CoverFront, Some(mime), None, data.to_vec())
+}
@@ -0,0 +1,215 @@
+use crate::config::Settings;
+use crate::models::Track;
+
+pub const DEFAULT_SINGLE: &str = "{artist} - {title}";
+pub const DEFAULT_ALBUM: &str = "{artist} - {album}/{artist} - {title}";
+pub const DEFAULT_PLAYLIST: &str = "{artist} - {title}";
+
+pub const FIELDS: &[&str] = &[
+    "title", "artist", "artists", "album", "album_artist", "track", "track_total", "disc",
+    "year", "genre", "label", "duration", "playlist",
+];
+
+#[derive(Debug, Clone)]
+enum Node {
+    Lit(String),
+    Sep,
+    Field { name: String, width: usize },
+    Cond(Vec<Node>),
+}
+
+/// the template that applies to `track`
+pub fn pick<'a>(track: &Track, cfg: &'a Settings) -> &'a str {
+    if track.is_album_track {
+        &cfg.tpl_album
+    } else if !track.playlist.is_empty() {
+        &cfg.tpl_playlist
+    } else {
+        &cfg.tpl_single
+    }
+}
+
+/// path components for `track`, the last one is the file name without extension.
+/// `{field}`, `{field:02}` zero-pads numbers, `[...]` is dropped when a field
+/// inside it is empty, `/` starts a folder and `\` escapes any of `{}[]/\`
+pub fn render(tpl: &str, track: &Track, joiner: &str) -> Result<Vec<String>, String> {
+    let nodes = parse(tpl)?;
+    let mut parts = vec![String::new()];
+    render_into(&nodes, track, joiner, &mut parts)?;
+    let parts: Vec<String> = parts.into_iter()
+        .map(|p| p.trim().to_string())
+        .filter(|p| !p.is_empty())
+        .collect();
+    if parts.is_empty() {
+        return Err(String::from("template renders to an empty name"));
+    }
+    Ok(parts)
+}
+
+/// like `render`, falling back to the default for the kind of track
+pub fn render_or_default(track: &Track, cfg: &Settings) -> Vec<String> {
+    render(pick(track, cfg), track, &cfg.artist_joiner)
+        .or_else(|_| render(pick(track, &Settings::default()), track, &cfg.artist_joiner))
+        .unwrap_or_else(|_| vec![format!("{} - {}", track.artist, track.title)])
+}
+
+fn value(track: &Track, name: &str, joiner: &str) -> Result<String, String> {
+    Ok(match name {
+        "title" => track.title.clone(),
+        "artist" => track.artist.clone(),
+        "artists" => track.artists_joined(joiner),
+        "album" => track.album.clone(),
+        "album_artist" => if track.album_artist.is_empty() { track.artist.clone() } else { track.album_artist.clone() },
+        "track" => track.track_pos.map_or(String::new(), |n| n.to_string()),
+        "track_total" => track.track_total.map_or(String::new(), |n| n.to_string()),
+        "disc" => track.disc.map_or(String::new(), |n| n.to_string()),
+        "year" => track.year().to_string(),
+        "genre" => track.genres.first().cloned().unwrap_or_default(),
+        "label" => track.label.clone(),
+        "duration" => if track.duration > 0.0 { track.duration_fmt() } else { String::new() },
+        "playlist" => track.playlist.clone(),
+        _ => return Err(format!("unknown field {{{name}}}")),
+    })
+}
+
+/// returns false when a field was empty, the caller drops conditional output then
+fn render_into(nodes: &[Node], track: &Track, joiner: &str, parts: &mut Vec<String>) -> Result<bool, String> {
+    let mut complete = true;
+    for n in nodes {
+        match n {
+            Node::Lit(s) => push(parts, s),
+            Node::Sep => parts.push(String::new()),
+            Node::Field { name, width } => {
+                let v = value(track, name, joiner)?;
+                if v.is_empty() { complete = false; }
+                let v = if *width > 0 && !v.is_empty() && v.chars().all(|c| c.is_ascii_digit()) {
+                    format!("{v:0>width$}", width = *width)
+                } else {
+                    v
+                };
+                push(parts, &v);
+            }
+            Node::Cond(inner) => {
+                let mut sub = vec![String::new()];
+                if render_into(inner, track, joiner, &mut sub)? {
+                    let mut sub = sub.into_iter();
+                    if let Some(first) = sub.next() { push(parts, &first); }
+                    parts.extend(sub);
+                }
+            }
+        }
+    }
+    Ok(complete)
+}
+
+fn push(parts: &mut [String], s: &str) {
+    if let Some(last) = parts.last_mut() { last.push_str(s); }
+}
+
+fn parse(tpl: &str) -> Result<Vec<Node>, String> {
+    parse_seq(&mut tpl.chars(), false)
+}
+
+fn parse_seq(chars: &mut std::str::Chars, in_cond: bool) -> Result<Vec<Node>, String> {
+    let mut nodes = Vec::new();
+    let mut lit = String::new();
+    let flush = |lit: &mut String, nodes: &mut Vec<Node>| {
+        if !lit.is_empty() { nodes.push(Node::Lit(std::mem::take(lit))); }
+    };
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => lit.push(chars.next().ok_or_else(|| String::from("trailing \\"))?),
+            '/' => { flush(&mut lit, &mut nodes); nodes.push(Node::Sep); }
+            '{' => {
+                flush(&mut lit, &mut nodes);
+                let mut body = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => body.push(c),
+                        None => return Err(String::from("unclosed {")),
+                    }
+                }
+                let (name, width) = match body.split_once(':') {
+                    Some((n, w)) => (n, w.parse().map_err(|_| format!("bad width in {{{body}}}"))?),
+                    None => (body.as_str(), 0),
+                };
+                nodes.push(Node::Field { name: name.trim().to_string(), width });
+            }
+            '[' => {
+                flush(&mut lit, &mut nodes);
+                nodes.push(Node::Cond(parse_seq(chars, true)?));
+            }
+            ']' if in_cond => {
+                flush(&mut lit, &mut nodes);
+                return Ok(nodes);
+            }
+            '}' | ']' => return Err(format!("unexpected {c}")),
+            c => lit.push(c),
+        }
+    }
+    if in_cond {
+        return Err(String::from("unclosed ["));
+    }
+    flush(&mut lit, &mut nodes);
+    Ok(nodes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track() -> Track {
+        Track {
+            title: String::from("Song"),
+            artist: String::from("Band"),
+            album: String::from("Record"),
+            track_pos: Some(3),
+            release_date: String::from("2001-02-03"),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn fields_and_folders() {
+        let parts = render("{artist} - {album}/{track:02} {title}", &track(), ", ").unwrap();
+        assert_eq!(parts, ["Band - Record", "03 Song"]);
+    }
+
+    #[test]
+    fn conditional_kept_when_fields_set() {
+        let parts = render("{title}[ ({year})]", &track(), ", ").unwrap();
+        assert_eq!(parts, ["Song (2001)"]);
+    }
+
+    #[test]
+    fn conditional_dropped_when_field_empty() {
+        let parts = render("[{disc}-]{title}[ ({label})]", &track(), ", ").unwrap();
+        assert_eq!(parts, ["Song"]);
+    }
+
+    #[test]
+    fn conditional_folder_dropped_when_field_empty() {
+        let parts = render("{artist}[/{genre}]/{title}", &track(), ", ").unwrap();
+        assert_eq!(parts, ["Band", "Song"]);
+    }
+
+    #[test]
+    fn escapes() {
+        let parts = render(r"\{title\}\[x\]\/\\", &track(), ", ").unwrap();
+        assert_eq!(parts, [r"{title}[x]/\"]);
+    }
+
+    #[test]
+    fn parse_errors() {
+        let t = track();
+        assert_eq!(render("{title", &t, ", ").unwrap_err(), "unclosed {");
+        assert_eq!(render("[{title}", &t, ", ").unwrap_err(), "unclosed [");
+        assert_eq!(render("title}", &t, ", ").unwrap_err(), "unexpected }");
+        assert_eq!(render("title]", &t, ", ").unwrap_err(), "unexpected ]");
+        assert_eq!(render(r"title\", &t, ", ").unwrap_err(), "trailing \\");
+        assert_eq!(render("{track:x}", &t, ", ").unwrap_err(), "bad width in {track:x}");
+        assert_eq!(render("{nope}", &t, ", ").unwrap_err(), "unknown field {nope}");
+        assert_eq!(render("[{label}]", &t, ", ").unwrap_err(), "template renders to an empty name");
+    }
+}
@@ -1,141 +1,454 @@
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicU64, Ordering};
+use base64::{Engine, engine::general_purpose::STANDARD};
 use tokio::io::{AsyncBufReadExt, BufReader};
 use tokio::process::Command;
 
-use crate::config;
-use crate::models::Track;
-use super::deezer;
+use crate::config::{self, Settings};
+use crate::models::progress::{Phase, Progress};
+use crate::models::{format, AudioFormat, Track};
+use super::gate::Gate;
+use super::loudness::{self, GainMode};
+use super::{covers, history, lyrics, matcher, sanitize, tags, template};
 
 static COUNTER: AtomicU64 = AtomicU64::new(0);
 
-pub async fn download<F>(track: &Track, base: &Path, on_progress: F) -> Result<String, String>
+/// name prefix of everything a download writes before the final rename
+pub const PARTIAL_PREFIX: &str = ".mdl_";
+
+/// temp files of one download, removed on drop unless it finished.
+/// cancelling aborts the task, so this is also the cancel cleanup
+struct Partial {
+    dir: PathBuf,
+    stem: String,
+    cover: Option<PathBuf>,
+    done: bool,
+}
+
+impl Drop for Partial {
+    fn drop(&mut self) {
+        if let Some(c) = &self.cover {
+            let _ = fs::remove_file(c);
+            let _ = fs::remove_file(c.with_extension("ffmeta"));
+        }
+        if !self.done {
+            // the dot keeps job 1 from matching the files of jobs 10 and up
+            remove_partials(&self.dir, &format!("{}.", self.stem));
+        }
+    }
+}
+
+pub fn remove_partials(dir: &Path, prefix: &str) {
+    let Ok(entries) = fs::read_dir(dir) else { return };
+    for e in entries.flatten() {
+        if e.file_name().to_string_lossy().starts_with(prefix) {
+            let _ = fs::remove_file(e.path());
+        }
+    }
+}
+
+/// the final path of the file and the log of every stage
+pub async fn download<F>(
+    track: &Track,
+    base: &Path,
+    cfg: &Settings,
+    attempt: u32,
+    gate: &Gate,
+    on_progress: F,
+) -> Result<(PathBuf, String), String>
 where
-    F: Fn(f64) + Send + 'static,
+    F: Fn(Progress) + Send + 'static,
 {
-    let dir = track_dir(base, track);
-    let query = format!("ytsearch1:{}", track.yt_query());
-    let tpl = dir.join("%(title)s.%(ext)s").to_string_lossy().to_string();
+    let fmt = cfg.format;
+    let ext = fmt.ext();
+    let dir = track_dir(base, track, cfg);
+    let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+    let mut partial = Partial {
+        stem: format!("{PARTIAL_PREFIX}{}_{n}", std::process::id()),
+        dir: dir.clone(),
+        cover: None,
+        done: false,
+    };
+    let tpl = dir.join(format!("{}.%(ext)s", partial.stem)).to_string_lossy().to_string();
+    let mut log = String::new();
 
-    let mut child = Command::new(config::ytdlp_bin())
+    let url = if track.source_url.is_empty() {
+        on_progress(Progress::phase(Phase::Searching));
+        pick_match(track, cfg, attempt, &mut log).await?
+    } else {
+        log.push_str(&format!("[match] direct {}\n", track.source_url));
+        track.source_url.clone()
+    };
+
+    let mut cmd = Command::new(config::ytdlp_bin());
+    cmd.args(["-x", "--audio-format", fmt.codec()]);
+    if !fmt.lossless() {
+        cmd.args(["--audio-quality", &format::quality_arg(cfg.bitrate)]);
+    }
+    if cfg.rate_limit > 0 {
+        let per_worker = (cfg.rate_limit / cfg.parallel.max(1)).max(1);
+        cmd.args(["--limit-rate", &format!("{per_worker}K")]);
+    }
+    #[cfg(unix)]
+    cmd.process_group(0);
+    let mut child = cmd
         .args([
-            "-x", "--audio-format", "mp3", "--audio-quality", "0",
             "--no-embed-metadata", "--no-embed-thumbnail",
             "--no-warnings", "--no-playlist",
             "--newline", "--progress",
+            "--progress-template", PROGRESS_TEMPLATE,
+            "--progress-template", PP_TEMPLATE,
             "--print", "after_move:filepath",
             "-o", &tpl,
         ])
-        .arg(&query)
+        .arg(&url)
         .stdout(std::process::Stdio::piped())
         .stderr(std::process::Stdio::piped())
+        .kill_on_drop(true)
         .spawn().map_err(|e| format!("spawn: {e}"))?;
+    let mut proc = gate.track(child.id());
 
-    let mut mp3_path = None;
-    let mut log = String::new();
-
-    if let Some(stdout) = child.stdout.take() {
+    // both pipes at once, a child blocked on a full stderr never closes stdout
+    let stdout = child.stdout.take();
+    let stderr = child.stderr.take();
+    let read_out = async {
+        let mut out_log = String::new();
+        let mut out_path = None;
+        let Some(stdout) = stdout else { return (out_log, out_path) };
         let mut lines = BufReader::new(stdout).lines();
         while let Ok(Some(line)) = lines.next_line().await {
-            log.push_str(&line);
-            log.push('\n');
-            if let Some(pct) = parse_pct(&line) { on_progress(pct); }
+            if let Some(p) = parse_progress(&line) {
+                on_progress(p);
+                continue;
+            }
+            out_log.push_str(&line);
+            out_log.push('\n');
             let trimmed = line.trim();
-            if trimmed.ends_with(".mp3") && Path::new(trimmed).exists() {
-                mp3_path = Some(PathBuf::from(trimmed));
+            if trimmed.ends_with(&format!(".{ext}")) && Path::new(trimmed).exists() {
+                out_path = Some(PathBuf::from(trimmed));
             }
         }
-    }
-
-    if let Some(stderr) = child.stderr.take() {
+        (out_log, out_path)
+    };
+    let read_err = async {
+        let mut err_log = String::new();
+        let Some(stderr) = stderr else { return err_log };
         let mut lines = BufReader::new(stderr).lines();
         while let Ok(Some(line)) = lines.next_line().await {
-            log.push_str("[stderr] ");
-            log.push_str(&line);
-            log.push('\n');
+            err_log.push_str("[stderr] ");
+            err_log.push_str(&line);
+            err_log.push('\n');
         }
-    }
+        err_log
+    };
+    let ((out_log, out_path), err_log) = tokio::join!(read_out, read_err);
+    log.push_str(&out_log);
+    log.push_str(&err_log);
 
     let status = child.wait().await.map_err(|e| format!("wait: {e}"))?;
+    if let Some(p) = proc.as_mut() { p.finish(); }
+    drop(proc);
     if !status.success() { return Err(format!("yt-dlp failed\n{log}")); }
 
-    let mp3 = mp3_path.ok_or_else(|| format!("mp3 not found\n{log}"))?;
-    on_progress(90.0);
+    let file = out_path.ok_or_else(|| format!("{ext} not found\n{log}"))?;
 
-    let cover = fetch_cover_tmp(track).await;
-    embed_meta(&mp3, track, cover.as_deref()).await?;
+    // before tagging, applying the gain re-encodes the file
+    let scan = match cfg.gain_mode {
+        GainMode::Off => None,
+        mode => {
+            on_progress(Progress::phase(Phase::Converting));
+            measure(&file, cfg, mode, &mut log).await
+        }
+    };
+
+    on_progress(Progress::phase(Phase::Tagging));
 
-    let final_path = dir.join(format!("{} - {}.mp3", track.artist, track.title));
-    if final_path != mp3 { let _ = fs::rename(&mp3, &final_path); }
+    let cover = covers::get(&track.cover_url, cfg).await;
+    if let Err(e) = write_tags(&file, track, cfg, cover.clone()).await {
+        log.push_str(&format!("[tags] {e}, falling back to ffmpeg\n"));
+        partial.cover = cover.as_deref().and_then(cover_tmp);
+        embed_meta(&file, track, cfg, partial.cover.as_deref()).await?;
+    }
 
-    on_progress(100.0);
-    Ok(log)
+    if cfg.lyrics_embed || cfg.lyrics_lrc {
+        add_lyrics(&file, track, cfg, &mut log).await;
+    }
+    if let Some(s) = scan {
+        let f = file.clone();
+        match tokio::task::spawn_blocking(move || tags::set_gain(&f, Some(&s), None)).await {
+            Ok(Ok(())) => log.push_str(&format!("[gain] track {:+.2} dB, peak {:.3}\n", s.gain(), s.peak())),
+            Ok(Err(e)) => log.push_str(&format!("[gain] {e}\n")),
+            Err(e) => log.push_str(&format!("[gain] tag task: {e}\n")),
+        }
+    }
+
+    let final_path = target_path(base, track, cfg, fmt);
+    if final_path != file {
+        fs::rename(&file, &final_path).map_err(|e| format!("rename: {e}"))?;
+    }
+    let lrc = file.with_extension("lrc");
+    if lrc.exists() { let _ = fs::rename(&lrc, final_path.with_extension("lrc")); }
+    partial.done = true;
+    history::record(track, &final_path);
+    if cfg.folder_art && track.is_album_track {
+        if let (Some(data), Some(dir)) = (&cover, final_path.parent()) {
+            covers::write_folder_art(dir, data);
+        }
+    }
+    if let Some(s) = scan { loudness::remember(&final_path, s); }
+
+    Ok((final_path, log))
 }
 
-fn track_dir(base: &Path, track: &Track) -> PathBuf {
-    if track.is_album_track && !track.album.is_empty() && !track.artist.is_empty() {
-        let dir = base.join(format!("{} - {}", track.artist, track.album));
-        let _ = fs::create_dir_all(&dir);
-        dir
-    } else {
-        base.to_path_buf()
+/// url of the best search candidate for this attempt
+async fn pick_match(track: &Track, cfg: &Settings, attempt: u32, log: &mut String) -> Result<String, String> {
+    let (prefix, pick) = matcher::plan(attempt);
+    let wanted = cfg.candidates.max(pick as u32 + 1);
+    let list = matcher::candidates(prefix, &track.yt_query(), wanted).await?;
+    let ranked = matcher::rank(track, list);
+    log.push_str(&format!("[match] {prefix}, attempt {}\n", attempt + 1));
+    for (c, score) in &ranked {
+        log.push_str(&format!("[match] {score:>6.1}  {}\n", c.describe()));
+    }
+    let (best, best_score) = ranked.get(pick).or(ranked.last())
+        .ok_or_else(|| String::from("no candidates"))?;
+    log.push_str(&format!("[match] chose {} (score {best_score:.1})\n", best.describe()));
+    Ok(best.url())
+}
+
+fn track_dir(base: &Path, track: &Track, cfg: &Settings) -> PathBuf {
+    let dir = target_dir(base, track, cfg);
+    let _ = fs::create_dir_all(&dir);
+    dir
+}
+
+/// where the finished file goes, from the name template for this kind of track
+pub fn target_path(base: &Path, track: &Track, cfg: &Settings, fmt: AudioFormat) -> PathBuf {
+    let mut parts = template::render_or_default(track, cfg);
+    let name = parts.pop().unwrap_or_default();
+    let mut path = base.to_path_buf();
+    for part in parts {
+        path.push(sanitize::component(&part, None, cfg.name_profile, cfg.ascii_names));
     }
+    path.push(sanitize::component(&name, Some(fmt.ext()), cfg.name_profile, cfg.ascii_names));
+    path
+}
+
+pub fn target_dir(base: &Path, track: &Track, cfg: &Settings) -> PathBuf {
+    target_path(base, track, cfg, cfg.format)
+        .parent()
+        .map_or_else(|| base.to_path_buf(), Path::to_path_buf)
 }
 
-async fn embed_meta(mp3: &Path, track: &Track, cover: Option<&Path>) -> Result<(), String> {
-    let tmp = mp3.with_extension("tmp.mp3");
+/// loudness scan, and the gain baked in for `Apply`. only the scan for
+/// `Tags` is returned, applied audio must not be adjusted again by players.
+/// a failed scan is logged and leaves the file as it was
+async fn measure(file: &Path, cfg: &Settings, mode: GainMode, log: &mut String) -> Option<loudness::Scan> {
+    let scan = match loudness::scan(file).await {
+        Ok(s) => s,
+        Err(e) => { log.push_str(&format!("[gain] {e}\n")); return None; }
+    };
+    if mode == GainMode::Tags {
+        return Some(scan);
+    }
+    match loudness::apply(file, cfg.format, cfg.bitrate, &scan).await {
+        Ok(gain) => log.push_str(&format!("[gain] applied {gain:+.2} dB ({:.1} LUFS)\n", scan.lufs)),
+        Err(e) => log.push_str(&format!("[gain] {e}\n")),
+    }
+    None
+}
+
+/// lyrics are a bonus, every failure here is only logged
+async fn add_lyrics(file: &Path, track: &Track, cfg: &Settings, log: &mut String) {
+    let found = match lyrics::fetch(&cfg.lyrics_url, track).await {
+        Ok(l) if l.instrumental => { log.push_str("[lyrics] instrumental\n"); return; }
+        Ok(l) => l,
+        Err(e) => { log.push_str(&format!("[lyrics] {e}\n")); return; }
+    };
+    if cfg.lyrics_embed {
+        if let Some(text) = found.plain() {
+            let file = file.to_path_buf();
+            let res = tokio::task::spawn_blocking(move || tags::set_lyrics(&file, &text)).await;
+            match res.map_err(|e| format!("tag task: {e}")).and_then(|r| r) {
+                Ok(()) => log.push_str("[lyrics] embedded\n"),
+                Err(e) => log.push_str(&format!("[lyrics] embed: {e}\n")),
+            }
+        }
+    }
+    if cfg.lyrics_lrc {
+        match found.synced() {
+            Some(lrc) => match fs::write(file.with_extension("lrc"), lrc) {
+                Ok(()) => log.push_str("[lyrics] wrote .lrc\n"),
+                Err(e) => log.push_str(&format!("[lyrics] lrc: {e}\n")),
+            },
+            None => log.push_str("[lyrics] no synced lyrics\n"),
+        }
+    }
+}
+
+async fn write_tags(file: &Path, track: &Track, cfg: &Settings, cover: Option<Vec<u8>>) -> Result<(), String> {
+    let (file, track, cfg) = (file.to_path_buf(), track.clone(), cfg.clone());
+    tokio::task::spawn_blocking(move || tags::write(&file, &track, &cfg, cover.as_deref()))
+        .await
+        .map_err(|e| format!("tag task: {e}"))?
+}
+
+/// ffmpeg remux, only used when the native tag writer can't handle the file
+async fn embed_meta(file: &Path, track: &Track, cfg: &Settings, cover: Option<&Path>) -> Result<(), String> {
+    let fmt = cfg.format;
+    let tmp = file.with_extension(format!("tmp.{}", fmt.ext()));
 
     let mut args: Vec<String> = vec![
-        "-y".into(), "-i".into(), mp3.to_string_lossy().into(),
+        "-y".into(), "-i".into(), file.to_string_lossy().into(),
     ];
 
-    if let Some(c) = cover {
+    // ogg has no attached picture stream, the cover goes in as a METADATA_BLOCK_PICTURE comment
+    let ogg_cover = match cover {
+        Some(c) if matches!(fmt, AudioFormat::Opus | AudioFormat::Vorbis) => Some(write_ffmeta_cover(c)?),
+        _ => None,
+    };
+    let cover_stream = cover.is_some() && ogg_cover.is_none();
+
+    if let Some(meta) = &ogg_cover {
+        args.extend(["-f".into(), "ffmetadata".into(), "-i".into(), meta.to_string_lossy().into()]);
+        args.extend(["-map".into(), "0:a".into(), "-map_metadata".into(), "1".into()]);
+    } else if let Some(c) = cover {
         args.extend(["-i".into(), c.to_string_lossy().into()]);
         args.extend(["-map".into(), "0:a".into(), "-map".into(), "1:0".into()]);
     } else {
         args.extend(["-map".into(), "0:a".into()]);
     }
 
-    args.extend(["-c".into(), "copy".into(), "-id3v2_version".into(), "3".into()]);
-    args.extend(["-metadata".into(), format!("title={}", track.title)]);
-    args.extend(["-metadata".into(), format!("artist={}", track.artist)]);
-    args.extend(["-metadata".into(), format!("album={}", track.album)]);
-    if let Some(pos) = track.track_pos {
-        args.extend(["-metadata".into(), format!("track={pos}")]);
+    args.extend(["-c".into(), "copy".into()]);
+    if fmt == AudioFormat::Mp3 {
+        args.extend(["-id3v2_version".into(), "3".into()]);
+    }
+    for (key, val) in tag_pairs(track, cfg) {
+        args.extend(["-metadata".into(), format!("{key}={val}")]);
     }
-    if cover.is_some() {
+    if cover_stream {
+        args.extend(["-disposition:v".into(), "attached_pic".into()]);
         args.extend(["-metadata:s:v".into(), "title=Album cover".into()]);
         args.extend(["-metadata:s:v".into(), "comment=Cover (front)".into()]);
     }
     args.push(tmp.to_string_lossy().into());
 
-    let out = Command::new("ffmpeg").args(&args).output().await
+    let out = Command::new("ffmpeg").args(&args).kill_on_drop(true).output().await
         .map_err(|e| format!("ffmpeg: {e}"))?;
 
     if let Some(c) = cover { let _ = fs::remove_file(c); }
+    if let Some(m) = &ogg_cover { let _ = fs::remove_file(m); }
 
     if !out.status.success() {
         let _ = fs::remove_file(&tmp);
         return Err(format!("ffmpeg err: {}", String::from_utf8_lossy(&out.stderr)));
     }
 
-    fs::rename(&tmp, mp3).map_err(|e| format!("rename: {e}"))
+    fs::rename(&tmp, file).map_err(|e| format!("rename: {e}"))
 }
 
-async fn fetch_cover_tmp(track: &Track) -> Option<PathBuf> {
-    if track.cover_url.is_empty() { return None; }
-    let data = deezer::fetch_cover(&track.cover_url).await.ok()?;
+/// tag keys per container, ffmpeg maps these onto id3 frames, vorbis comments or mp4 atoms
+/// artists are always joined here, ffmpeg has no way to repeat a key
+fn tag_pairs(track: &Track, cfg: &Settings) -> Vec<(&'static str, String)> {
+    let vorbis = cfg.format.vorbis_tags();
+    let album_artist = if track.album_artist.is_empty() { &track.artist } else { &track.album_artist };
+    let mut tags = vec![
+        (if vorbis { "TITLE" } else { "title" }, track.title.clone()),
+        (if vorbis { "ARTIST" } else { "artist" }, track.artists_joined(&cfg.artist_joiner)),
+        (if vorbis { "ALBUM" } else { "album" }, track.album.clone()),
+    ];
+    if let Some(pos) = track.track_pos {
+        let pos = match track.track_total {
+            Some(total) if !vorbis => format!("{pos}/{total}"),
+            _ => pos.to_string(),
+        };
+        tags.push((if vorbis { "TRACKNUMBER" } else { "track" }, pos));
+    }
+    if let (true, Some(total)) = (vorbis, track.track_total) {
+        tags.push(("TRACKTOTAL", total.to_string()));
+    }
+    if let Some(disc) = track.disc {
+        tags.push((if vorbis { "DISCNUMBER" } else { "disc" }, disc.to_string()));
+    }
+    let text = [
+        (if vorbis { "ALBUMARTIST" } else { "album_artist" }, album_artist),
+        (if vorbis { "DATE" } else { "date" }, &track.release_date),
+        (if vorbis { "ISRC" } else { "TSRC" }, &track.isrc),
+        (if vorbis { "LABEL" } else { "publisher" }, &track.label),
+    ];
+    tags.extend(text.into_iter().filter(|(_, v)| !v.is_empty()).map(|(k, v)| (k, v.clone())));
+    if !track.genres.is_empty() {
+        tags.push((if vorbis { "GENRE" } else { "genre" }, track.genres.join("; ")));
+    }
+    tags
+}
+
+fn write_ffmeta_cover(cover: &Path) -> Result<PathBuf, String> {
+    let data = fs::read(cover).map_err(|e| format!("cover read: {e}"))?;
+    let block = STANDARD.encode(picture_block(&data));
+    let path = cover.with_extension("ffmeta");
+    // '=' is the only base64 char ffmetadata needs escaped
+    let body = format!(";FFMETADATA1\nMETADATA_BLOCK_PICTURE={}\n", block.replace('=', "\\="));
+    fs::write(&path, body).map_err(|e| format!("ffmeta write: {e}"))?;
+    Ok(path)
+}
+
+/// flac METADATA_BLOCK_PICTURE body for a front cover
+fn picture_block(data: &[u8]) -> Vec<u8> {
+    let mime = covers::mime(data).as_bytes();
+    let mut out = Vec::with_capacity(data.len() + 64);
+    out.extend_from_slice(&3u32.to_be_bytes());
+    out.extend_from_slice(&(mime.len() as u32).to_be_bytes());
+    out.extend_from_slice(mime);
+    out.extend_from_slice(&0u32.to_be_bytes());
+    // width, height, depth, colors are optional and left at 0
+    out.extend_from_slice(&[0u8; 16]);
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    out.extend_from_slice(data);
+    out
+}
+
+fn cover_tmp(data: &[u8]) -> Option<PathBuf> {
     let n = COUNTER.fetch_add(1, Ordering::Relaxed);
     let path = std::env::temp_dir().join(format!("mdl_cover_{}_{n}.jpg", std::process::id()));
-    fs::write(&path, &data).ok()?;
+    fs::write(&path, data).ok()?;
     Some(path)
 }
 
-fn parse_pct(line: &str) -> Option<f64> {
-    if !line.contains("[download]") { return None; }
-    line.split_whitespace()
-        .find(|s| s.ends_with('%'))?
-        .trim_end_matches('%')
-        .parse().ok()
+/// download progress as one json line, missing values default to 0
+const PROGRESS_TEMPLATE: &str = concat!(
+    "download:[mdl-dl] {",
+    r#""done":%(progress.downloaded_bytes|0)s,"#,
+    r#""total":%(progress.total_bytes|0)s,"#,
+    r#""estimate":%(progress.total_bytes_estimate|0)s,"#,
+    r#""speed":%(progress.speed|0)s,"#,
+    r#""eta":%(progress.eta|0)s}"#,
+);
+/// post-processing only says that it started, that's the converting phase
+const PP_TEMPLATE: &str = "postprocess:[mdl-pp] %(progress.status)s";
+
+#[derive(serde::Deserialize)]
+struct RawProgress {
+    done: f64,
+    total: f64,
+    estimate: f64,
+    speed: f64,
+    eta: f64,
+}
+
+fn parse_progress(line: &str) -> Option<Progress> {
+    if line.starts_with("[mdl-pp]") {
+        return Some(Progress::phase(Phase::Converting));
+    }
+    let raw: RawProgress = serde_json::from_str(line.strip_prefix("[mdl-dl] ")?).ok()?;
+    let total = if raw.total > 0.0 { raw.total } else { raw.estimate };
+    Some(Progress {
+        phase: Phase::Downloading,
+        pct: if total > 0.0 { (raw.done / total * 100.0).min(100.0) } else { 0.0 },
+        done: raw.done as u64,
+        total: total as u64,
+        speed: raw.speed,
+        eta: (raw.eta > 0.0).then_some(raw.eta as u64),
+    })
 }
@@ -1,4 +1,10 @@
 use std::path::PathBuf;
+use serde::{Deserialize, Serialize};
+
+use crate::backend::loudness::GainMode;
+use crate::backend::sanitize::NameProfile;
+use crate::backend::{lyrics, spotify, template};
+use crate::models::AudioFormat;
 
 pub fn dl_dir() -> PathBuf {
     dirs::audio_dir().unwrap_or_else(|| dirs::home_dir().unwrap_or_default().join("Music"))
@@ -10,6 +16,20 @@ pub fn data_dir() -> PathBuf {
         .join("music-downloader")
 }
 
+pub fn cache_dir() -> PathBuf {
+    dirs::cache_dir()
+        .unwrap_or_else(|| dirs::home_dir().unwrap_or_default().join(".cache"))
+        .join("music-downloader")
+}
+
+pub fn cover_cache_dir() -> PathBuf {
+    cache_dir().join("covers")
+}
+
+pub fn thumb_cache_dir() -> PathBuf {
+    cache_dir().join("thumbs")
+}
+
 pub fn ytdlp_path() -> PathBuf {
     data_dir().join("yt-dlp")
 }
@@ -26,3 +46,96 @@ pub fn ytdlp_bin() -> String {
 pub fn spotify_tokens_path() -> PathBuf {
     data_dir().join("spotify_tokens.json")
 }
+
+pub fn queue_path() -> PathBuf {
+    data_dir().join("queue.json")
+}
+
+pub fn history_path() -> PathBuf {
+    data_dir().join("history.json")
+}
+
+pub fn settings_path() -> PathBuf {
+    data_dir().join("settings.json")
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub format: AudioFormat,
+    pub bitrate: u32,
+    pub candidates: u32,
+    pub attempts: u32,
+    pub retry_delay: u32,
+    pub suspend_on_pause: bool,
+    pub parallel: u32,
+    /// KiB/s shared by all workers, 0 for no limit
+    pub rate_limit: u32,
+    pub skip_existing: bool,
+    pub tpl_single: String,
+    pub tpl_album: String,
+    pub tpl_playlist: String,
+    pub name_profile: NameProfile,
+    pub ascii_names: bool,
+    /// between artists wherever they end up in one string
+    pub artist_joiner: String,
+    /// one artist tag value per artist where the container allows it
+    pub multi_artist: bool,
+    /// base url of an lrclib compatible lyrics api
+    pub lyrics_url: String,
+    pub lyrics_embed: bool,
+    /// write synced lyrics to a .lrc next to the file
+    pub lyrics_lrc: bool,
+    pub gain_mode: GainMode,
+    /// longest cover side in px before embedding, 0 keeps the original
+    pub cover_max: u32,
+    pub cover_cache_mb: u32,
+    /// cover.jpg and folder.jpg in album folders
+    pub folder_art: bool,
+    /// loopback port for the spotify login redirect
+    pub sp_port: u16,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            format: AudioFormat::Mp3,
+            bitrate: 0,
+            candidates: 5,
+            attempts: 3,
+            retry_delay: 5,
+            suspend_on_pause: false,
+            parallel: 3,
+            rate_limit: 0,
+            skip_existing: true,
+            tpl_single: template::DEFAULT_SINGLE.into(),
+            tpl_album: template::DEFAULT_ALBUM.into(),
+            tpl_playlist: template::DEFAULT_PLAYLIST.into(),
+            name_profile: NameProfile::default(),
+            ascii_names: false,
+            artist_joiner: String::from("; "),
+            multi_artist: true,
+            lyrics_url: lyrics::DEFAULT_URL.into(),
+            lyrics_embed: true,
+            lyrics_lrc: false,
+            gain_mode: GainMode::default(),
+            cover_max: 0,
+            cover_cache_mb: 200,
+            folder_art: true,
+            sp_port: spotify::DEFAULT_PORT,
+        }
+    }
+}
+
+pub fn load_settings() -> Settings {
+    std::fs::read_to_string(settings_path())
+        .ok()
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_default()
+}
+
+pub fn save_settings(settings: &Settings) {
+    let path = settings_path();
+    let _ = std::fs::create_dir_all(path.parent().unwrap_or(&path));
+    let _ = std::fs::write(&path, serde_json::to_string_pretty(settings).unwrap_or_default());
+}
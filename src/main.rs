@@ -9,4 +9,6 @@ use ui::App;
 fn main() {
     let app = RelmApp::new("com.musicdownloader.app");
     app.run::<App>(());
+    // the last queue change may still be waiting out the save delay
+    backend::queue::flush();
 }
@@ -1,12 +1,16 @@
 use serde::Deserialize;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct Album {
     pub id: u64,
     pub title: String,
     pub artist: String,
     pub cover_url: String,
+    pub thumb_url: String,
     pub nb_tracks: u32,
+    pub release_date: String,
+    /// spotify album id, empty for deezer albums. `id` is 0 when set
+    pub sp_id: String,
 }
 
 #[derive(Debug, Deserialize)]
@@ -26,7 +30,27 @@ pub struct DzAlbum {
     #[serde(default)]
     pub cover_xl: String,
     #[serde(default)]
+    pub cover_small: String,
+    #[serde(default)]
     pub artist: Option<DzAlbumArtist>,
+    #[serde(default)]
+    pub release_date: String,
+    #[serde(default)]
+    pub label: String,
+    #[serde(default)]
+    pub genres: Option<DzGenres>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DzGenres {
+    #[serde(default)]
+    pub data: Vec<DzGenre>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DzGenre {
+    #[serde(default)]
+    pub name: String,
 }
 
 #[derive(Debug, Deserialize)]
@@ -42,7 +66,10 @@ impl Album {
             title: da.title.clone(),
             artist: da.artist.as_ref().map_or(String::new(), |a| a.name.clone()),
             cover_url: da.cover_xl.clone(),
+            thumb_url: da.cover_small.clone(),
             nb_tracks: da.nb_tracks,
+            release_date: da.release_date.clone(),
+            sp_id: String::new(),
         }
     }
 }
@@ -1,10 +1,12 @@
 use serde::Deserialize;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct Artist {
     pub id: u64,
     pub name: String,
     pub nb_album: u32,
+    /// spotify artist id, empty for deezer artists
+    pub sp_id: String,
 }
 
 #[derive(Debug, Deserialize)]
@@ -29,6 +31,7 @@ impl Artist {
             id: da.id,
             name: da.name.clone(),
             nb_album: da.nb_album,
+            sp_id: String::new(),
         }
     }
 }
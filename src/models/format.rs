@@ -0,0 +1,74 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum AudioFormat {
+    #[default]
+    Mp3,
+    Flac,
+    Opus,
+    M4a,
+    Vorbis,
+}
+
+impl AudioFormat {
+    pub const ALL: [AudioFormat; 5] = [Self::Mp3, Self::Flac, Self::Opus, Self::M4a, Self::Vorbis];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Mp3 => "MP3",
+            Self::Flac => "FLAC",
+            Self::Opus => "Opus",
+            Self::M4a => "M4A (AAC)",
+            Self::Vorbis => "Ogg Vorbis",
+        }
+    }
+
+    /// value for yt-dlp's `--audio-format`
+    pub fn codec(self) -> &'static str {
+        match self {
+            Self::Mp3 => "mp3",
+            Self::Flac => "flac",
+            Self::Opus => "opus",
+            Self::M4a => "m4a",
+            Self::Vorbis => "vorbis",
+        }
+    }
+
+    pub fn ext(self) -> &'static str {
+        match self {
+            Self::Mp3 => "mp3",
+            Self::Flac => "flac",
+            Self::Opus => "opus",
+            Self::M4a => "m4a",
+            Self::Vorbis => "ogg",
+        }
+    }
+
+    pub fn lossless(self) -> bool {
+        self == Self::Flac
+    }
+
+    /// tags are vorbis comments rather than id3 or mp4 atoms
+    pub fn vorbis_tags(self) -> bool {
+        matches!(self, Self::Flac | Self::Opus | Self::Vorbis)
+    }
+
+    pub fn index(self) -> u32 {
+        Self::ALL.iter().position(|f| *f == self).unwrap_or(0) as u32
+    }
+
+    pub fn from_index(i: u32) -> Self {
+        Self::ALL.get(i as usize).copied().unwrap_or_default()
+    }
+}
+
+/// bitrates offered in settings, 0 means best vbr
+pub const BITRATES: [u32; 5] = [0, 320, 256, 192, 128];
+
+pub fn quality_arg(kbps: u32) -> String {
+    if kbps == 0 { String::from("0") } else { format!("{kbps}K") }
+}
+
+pub fn bitrate_label(kbps: u32) -> String {
+    if kbps == 0 { String::from("Best (VBR)") } else { format!("{kbps} kbps") }
+}
@@ -1,7 +1,10 @@
 pub mod album;
 pub mod artist;
+pub mod format;
+pub mod progress;
 pub mod track;
 
 pub use album::Album;
 pub use artist::Artist;
+pub use format::AudioFormat;
 pub use track::{DlStatus, Track};
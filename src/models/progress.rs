@@ -0,0 +1,55 @@
+/// stage of a running download
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Phase {
+    #[default]
+    Searching,
+    Downloading,
+    Converting,
+    Tagging,
+}
+
+impl Phase {
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Searching => "searching",
+            Self::Downloading => "downloading",
+            Self::Converting => "converting",
+            Self::Tagging => "tagging",
+        }
+    }
+}
+
+/// one progress report, byte counts are 0 until yt-dlp knows them
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Progress {
+    pub phase: Phase,
+    pub pct: f64,
+    pub done: u64,
+    pub total: u64,
+    /// bytes per second
+    pub speed: f64,
+    /// seconds left on this track
+    pub eta: Option<u64>,
+}
+
+impl Progress {
+    pub fn phase(phase: Phase) -> Self {
+        let pct = if phase == Phase::Searching { 0.0 } else { 100.0 };
+        Self { phase, pct, ..Self::default() }
+    }
+}
+
+pub fn fmt_bytes(n: f64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut n = n;
+    let mut unit = 0;
+    while n >= 1024.0 && unit < UNITS.len() - 1 {
+        n /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 { format!("{n:.0} {}", UNITS[0]) } else { format!("{n:.1} {}", UNITS[unit]) }
+}
+
+pub fn fmt_secs(secs: u64) -> String {
+    format!("{}:{:02}", secs / 60, secs % 60)
+}
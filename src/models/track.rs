@@ -1,22 +1,57 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Default)]
+use super::album::DzAlbum;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Track {
     pub title: String,
+    /// primary artist, used for folders, searching and as album artist fallback
     pub artist: String,
+    /// everyone credited, primary first
+    pub artists: Vec<String>,
     pub album: String,
     pub duration: f64,
     pub track_pos: Option<u32>,
     pub cover_url: String,
+    /// small cover for list rows
+    pub thumb_url: String,
     pub is_album_track: bool,
+    /// set when the track came from a playlist, picks the playlist name template
+    pub playlist: String,
+    /// exact page to download from instead of searching
+    pub source_url: String,
+    /// deezer ids, 0 when unknown. used to fill in the fields below before download
+    pub dz_id: u64,
+    pub dz_album_id: u64,
+    pub album_artist: String,
+    pub disc: Option<u32>,
+    pub track_total: Option<u32>,
+    pub isrc: String,
+    /// yyyy-mm-dd, or just the year when that's all the source knows
+    pub release_date: String,
+    pub genres: Vec<String>,
+    pub label: String,
+    pub explicit: bool,
+    /// 0 when unknown
+    pub bpm: u32,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum DlStatus {
     Queued,
     Active(f64),
+    Paused,
     Done,
     Failed(String),
+    Cancelled,
+    Skipped,
+}
+
+impl DlStatus {
+    pub fn finished(&self) -> bool {
+        matches!(self, Self::Done | Self::Failed(_) | Self::Cancelled | Self::Skipped)
+    }
 }
 
 impl Track {
@@ -37,27 +72,98 @@ impl Track {
         Self {
             title: dt.title.clone(),
             artist: dt.artist.as_ref().map_or(String::new(), |a| a.name.clone()),
+            artists: dz_artists(dt),
             album: dt.album.as_ref().map_or(album_fb.to_string(), |a| a.title.clone()),
             duration: dt.duration,
             track_pos: dt.track_position,
             cover_url: cover,
-            is_album_track: false,
+            thumb_url: dt.album.as_ref().map_or(String::new(), |a| a.cover_small.clone()),
+            dz_id: dt.id,
+            dz_album_id: dt.album.as_ref().map_or(0, |a| a.id),
+            disc: dt.disk_number,
+            isrc: dt.isrc.clone(),
+            release_date: dt.release_date.clone(),
+            explicit: dt.explicit_lyrics,
+            bpm: dt.bpm.filter(|b| *b > 0.0).map_or(0, |b| b.round() as u32),
+            ..Self::default()
+        }
+    }
+
+    /// every credited artist, old queue entries only have the primary one
+    pub fn all_artists(&self) -> Vec<String> {
+        if self.artists.is_empty() && !self.artist.is_empty() {
+            vec![self.artist.clone()]
+        } else {
+            self.artists.clone()
         }
     }
 
+    pub fn artists_joined(&self, joiner: &str) -> String {
+        self.all_artists().join(joiner)
+    }
+
+    /// release year for templates and tags
+    pub fn year(&self) -> &str {
+        self.release_date.get(..4).filter(|y| y.chars().all(|c| c.is_ascii_digit())).unwrap_or("")
+    }
+
+    /// fill in what the full deezer track lookup knows and we don't
+    pub fn merge_dz(&mut self, dt: &DzTrack) {
+        if self.dz_id == 0 { self.dz_id = dt.id; }
+        if self.dz_album_id == 0 { self.dz_album_id = dt.album.as_ref().map_or(0, |a| a.id); }
+        // search results carry no contributors, the full lookup does
+        if self.artists.len() <= 1 && !dt.contributors.is_empty() { self.artists = dz_artists(dt); }
+        if self.disc.is_none() { self.disc = dt.disk_number; }
+        if self.track_pos.is_none() { self.track_pos = dt.track_position; }
+        if self.isrc.is_empty() { self.isrc = dt.isrc.clone(); }
+        if self.release_date.is_empty() { self.release_date = dt.release_date.clone(); }
+        if self.bpm == 0 { self.bpm = dt.bpm.filter(|b| *b > 0.0).map_or(0, |b| b.round() as u32); }
+        self.explicit |= dt.explicit_lyrics;
+    }
+
+    /// album level fields, the album's own release date wins over the track's
+    pub fn merge_dz_album(&mut self, da: &DzAlbum) {
+        if self.album_artist.is_empty() {
+            self.album_artist = da.artist.as_ref().map_or(String::new(), |a| a.name.clone());
+        }
+        if self.track_total.is_none() && da.nb_tracks > 0 { self.track_total = Some(da.nb_tracks); }
+        if !da.release_date.is_empty() { self.release_date = da.release_date.clone(); }
+        if self.genres.is_empty() {
+            self.genres = da.genres.as_ref().map_or_else(Vec::new, |g| g.data.iter().map(|g| g.name.clone()).collect());
+        }
+        if self.label.is_empty() { self.label = da.label.clone(); }
+    }
+
     pub fn yt_query(&self) -> String {
         format!("{} - {}", self.artist, self.title)
     }
 }
 
+/// the track's artist first, then the other main contributors, then featured ones
+fn dz_artists(dt: &DzTrack) -> Vec<String> {
+    let mut out: Vec<String> = dt.artist.iter().map(|a| a.name.clone()).collect();
+    let (main, rest): (Vec<_>, Vec<_>) = dt.contributors.iter().partition(|c| c.role.eq_ignore_ascii_case("main"));
+    for c in main.into_iter().chain(rest) {
+        if !c.name.is_empty() && !out.contains(&c.name) {
+            out.push(c.name.clone());
+        }
+    }
+    out
+}
+
 #[derive(Debug, Deserialize)]
 pub struct DzTrackRes {
     #[serde(default)]
     pub data: Vec<DzTrack>,
+    /// next page for playlists, absent on the last one
+    #[serde(default)]
+    pub next: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct DzTrack {
+    #[serde(default)]
+    pub id: u64,
     #[serde(default)]
     pub title: String,
     #[serde(default)]
@@ -68,6 +174,26 @@ pub struct DzTrack {
     pub artist: Option<DzArtist>,
     #[serde(default)]
     pub album: Option<DzAlbumRef>,
+    #[serde(default)]
+    pub disk_number: Option<u32>,
+    #[serde(default)]
+    pub isrc: String,
+    #[serde(default)]
+    pub release_date: String,
+    #[serde(default)]
+    pub explicit_lyrics: bool,
+    #[serde(default)]
+    pub bpm: Option<f64>,
+    #[serde(default)]
+    pub contributors: Vec<DzContributor>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DzContributor {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub role: String,
 }
 
 #[derive(Debug, Deserialize)]
@@ -78,8 +204,12 @@ pub struct DzArtist {
 
 #[derive(Debug, Deserialize)]
 pub struct DzAlbumRef {
+    #[serde(default)]
+    pub id: u64,
     #[serde(default)]
     pub title: String,
     #[serde(default)]
     pub cover_xl: String,
+    #[serde(default)]
+    pub cover_small: String,
 }
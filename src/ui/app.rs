@@ -1,14 +1,21 @@
+use std::collections::HashMap;
 use std::path::PathBuf;
+use std::sync::Arc;
 use std::time::Instant;
 
 use adw::prelude::*;
 use relm4::prelude::*;
 use relm4::factory::FactoryVecDeque;
 
+use crate::backend::dupes::Dupe;
+use crate::backend::gate::Gate;
 use crate::backend::spotify;
+use crate::config::{self, Settings};
+use crate::models::progress::Progress;
 use crate::models::{Album, Artist, Track};
 use super::dialogs::LogHandle;
-use super::dl_row::DlRow;
+use super::dl::Batch;
+use super::dl_row::{DlRow, DlRowOutput};
 use super::handlers;
 use super::result_row::{ResultItem, ResultRow, ResultRowOutput};
 
@@ -16,22 +23,35 @@ pub struct App {
     pub results: FactoryVecDeque<ResultRow>,
     pub downloads: FactoryVecDeque<DlRow>,
     pub dl_dir: PathBuf,
+    pub settings: Settings,
     pub searching: bool,
     pub busy: bool,
     pub status: String,
     pub search_entry: gtk::SearchEntry,
     pub filter: gtk::DropDown,
+    /// deezer or spotify catalog
+    pub source: gtk::DropDown,
     pub logs: Vec<String>,
     pub next_dl_id: u64,
+    pub jobs: HashMap<u64, tokio::task::JoinHandle<()>>,
+    pub gate: Arc<Gate>,
+    pub paused_at: Option<Instant>,
+    pub restored: Vec<u64>,
     pub eta: String,
     pub dl_started: Option<Instant>,
     pub dl_total: usize,
     pub dl_done: usize,
+    /// tracks downloaded in this run and their bytes, for the eta
+    pub dl_files: usize,
+    pub dl_bytes: u64,
     pub sp_tokens: Option<spotify::Tokens>,
     pub log_handle: Option<LogHandle>,
     pub sp_row: Option<adw::ActionRow>,
     pub sp_conn_btn: Option<gtk::Button>,
     pub sp_disc_btn: Option<gtk::Button>,
+    pub sp_cancel_btn: Option<gtk::Button>,
+    /// login waiting on the browser, aborted on cancel
+    pub sp_auth: Option<tokio::task::JoinHandle<()>>,
 }
 
 #[derive(Debug)]
@@ -52,26 +72,43 @@ pub enum Msg {
     AlbumTracks(Result<Vec<Track>, String>),
     SelectAll,
     DeselectAll,
-
-    DlSelected,
-    DlStart(Vec<Track>),
-    DlProgress(u64, f64),
-    DlDone(u64, Result<String, String>),
+    EditResult(DynamicIndex),
+    ResultEdited(DynamicIndex, Track),
+
+    DlSelected(Batch),
+    DlSelectedLimits,
+    DlStart(Vec<Track>, Batch),
+    DlProgress(u64, Progress),
+    DlDone(u64, Result<(PathBuf, String), String>),
+    DlLog(String),
+    DlAttempt(u64, u32, u32),
+    DlSkipped(u64, Dupe),
+    DlRetry(u64),
+    DlCancel(u64),
+    DlCancelAll,
+    DlTogglePause,
+    QueueRestore,
+    QueueResume,
+    QueueDiscard,
 
     SetDlDir,
     DlDirPicked(PathBuf),
     ShowLogs,
     ShowSettings,
+    SettingsChanged(Settings),
     SettingsDone,
 
     SpConnect,
     SpAuth(Result<spotify::Tokens, String>),
+    SpCancel,
     SpDisconnect,
     SpLibrary,
     SpLibRes(Result<Vec<spotify::Playlist>, String>),
     SpPlaylist(String, String),
     SpLiked,
+    SpSection(spotify::Section),
     SpTracks(Result<Vec<Track>, String>),
+    SpArtists(Result<Vec<Artist>, String>),
 }
 
 #[relm4::component(pub)]
@@ -131,6 +168,11 @@ impl Component for App {
                         set_spacing: 8,
                         set_margin_all: 12,
 
+                        model.source.clone() {
+                            set_valign: gtk::Align::Center,
+                            set_tooltip_text: Some("Search in"),
+                        },
+
                         model.filter.clone() {
                             set_valign: gtk::Align::Center,
                         },
@@ -181,10 +223,16 @@ impl Component for App {
                                 },
                                 gtk::Button { set_label: "All", add_css_class: "flat", connect_clicked => Msg::SelectAll },
                                 gtk::Button { set_label: "None", add_css_class: "flat", connect_clicked => Msg::DeselectAll },
+                                gtk::Button {
+                                    set_icon_name: "document-save-as-symbolic",
+                                    add_css_class: "flat",
+                                    set_tooltip_text: Some("Download with limits"),
+                                    connect_clicked => Msg::DlSelectedLimits,
+                                },
                                 gtk::Button {
                                     set_icon_name: "document-save-symbolic",
                                     add_css_class: "suggested-action",
-                                    connect_clicked => Msg::DlSelected,
+                                    connect_clicked => Msg::DlSelected(Batch::default()),
                                 },
                             },
 
@@ -224,6 +272,8 @@ impl Component for App {
                             set_orientation: gtk::Orientation::Vertical,
 
                             gtk::Box {
+                                set_orientation: gtk::Orientation::Horizontal,
+                                set_spacing: 8,
                                 set_margin_all: 8,
                                 gtk::Label {
                                     set_label: "Downloads",
@@ -231,6 +281,25 @@ impl Component for App {
                                     set_halign: gtk::Align::Start,
                                     add_css_class: "title-4",
                                 },
+                                gtk::Button {
+                                    #[watch]
+                                    set_icon_name: if model.paused_at.is_some() {
+                                        "media-playback-start-symbolic"
+                                    } else {
+                                        "media-playback-pause-symbolic"
+                                    },
+                                    #[watch]
+                                    set_tooltip_text: Some(if model.paused_at.is_some() { "Resume queue" } else { "Pause queue" }),
+                                    add_css_class: "flat",
+                                    connect_clicked => Msg::DlTogglePause,
+                                },
+                                gtk::Button {
+                                    set_label: "Cancel all",
+                                    add_css_class: "flat",
+                                    #[watch]
+                                    set_sensitive: !model.jobs.is_empty(),
+                                    connect_clicked => Msg::DlCancelAll,
+                                },
                             },
 
                             gtk::ScrolledWindow {
@@ -287,35 +356,54 @@ impl Component for App {
                 ResultRowOutput::Album(a) => Msg::LoadAlbum(a),
                 ResultRowOutput::SpotifyPlaylist(id, name) => Msg::SpPlaylist(id, name),
                 ResultRowOutput::SpotifyLiked => Msg::SpLiked,
+                ResultRowOutput::SpotifySection(sec) => Msg::SpSection(sec),
+                ResultRowOutput::Edit(i) => Msg::EditResult(i),
             });
 
         let downloads = FactoryVecDeque::builder()
             .launch(gtk::ListBox::default())
-            .forward(sender.input_sender(), |_: ()| unreachable!());
+            .forward(sender.input_sender(), |out| match out {
+                DlRowOutput::Cancel(id) => Msg::DlCancel(id),
+                DlRowOutput::Retry(id) => Msg::DlRetry(id),
+            });
 
         let filter = gtk::DropDown::from_strings(&["All", "Albums", "Artists", "Tracks"]);
         filter.set_selected(0);
+        let source = gtk::DropDown::from_strings(&["Deezer", "Spotify"]);
+        source.set_selected(0);
 
+        let settings = config::load_settings();
+        let gate = Arc::new(Gate::new(settings.parallel as usize, settings.rate_limit));
         let model = App {
             results,
             downloads,
-            dl_dir: crate::config::dl_dir(),
+            dl_dir: config::dl_dir(),
+            settings,
             searching: false,
             busy: false,
             status: String::new(),
             search_entry: gtk::SearchEntry::new(),
             filter,
+            source,
             logs: Vec::new(),
             next_dl_id: 0,
+            jobs: HashMap::new(),
+            gate,
+            paused_at: None,
+            restored: Vec::new(),
             eta: String::new(),
             dl_started: None,
             dl_total: 0,
             dl_done: 0,
+            dl_files: 0,
+            dl_bytes: 0,
             sp_tokens: spotify::load_tokens(),
             log_handle: None,
             sp_row: None,
             sp_conn_btn: None,
             sp_disc_btn: None,
+            sp_cancel_btn: None,
+            sp_auth: None,
         };
 
         let result_list = model.results.widget();
@@ -323,6 +411,7 @@ impl Component for App {
         let widgets = view_output!();
 
         sender.input(Msg::CheckDeps);
+        sender.input(Msg::QueueRestore);
         ComponentParts { model, widgets }
     }
 
@@ -0,0 +1,47 @@
+use adw::prelude::*;
+
+use crate::models::Track;
+
+pub fn edit_track(
+    window: &adw::ApplicationWindow,
+    track: &Track,
+    on_save: impl Fn(Track) + 'static,
+) {
+    let d = adw::MessageDialog::new(
+        Some(window),
+        Some("edit tags"),
+        Some("used for the file name and tags of this download"),
+    );
+
+    let title_row = adw::EntryRow::builder().title("Title").text(&track.title).build();
+    let artist_row = adw::EntryRow::builder().title("Artist").text(&track.artist).build();
+    let album_row = adw::EntryRow::builder().title("Album").text(&track.album).build();
+
+    let list = gtk::ListBox::builder()
+        .selection_mode(gtk::SelectionMode::None)
+        .build();
+    list.add_css_class("boxed-list");
+    list.append(&title_row);
+    list.append(&artist_row);
+    list.append(&album_row);
+    d.set_extra_child(Some(&list));
+
+    d.add_response("cancel", "Cancel");
+    d.add_response("save", "Save");
+    d.set_response_appearance("save", adw::ResponseAppearance::Suggested);
+    d.set_close_response("cancel");
+    let track = track.clone();
+    d.connect_response(None, move |_, r| {
+        if r != "save" { return; }
+        let mut t = track.clone();
+        t.title = title_row.text().trim().to_string();
+        t.album = album_row.text().trim().to_string();
+        let artist = artist_row.text().trim().to_string();
+        if artist != t.artist {
+            t.artists = if artist.is_empty() { Vec::new() } else { vec![artist.clone()] };
+            t.artist = artist;
+        }
+        on_save(t);
+    });
+    d.present();
+}
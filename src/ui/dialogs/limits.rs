@@ -0,0 +1,44 @@
+use adw::prelude::*;
+
+pub fn batch_limits(
+    window: &adw::ApplicationWindow,
+    parallel: u32,
+    rate_limit: u32,
+    on_start: impl Fn(u32, u32) + 'static,
+) {
+    let d = adw::MessageDialog::new(
+        Some(window),
+        Some("download with limits"),
+        Some("overrides the settings for this batch only"),
+    );
+
+    // batch jobs also take a global slot, more than the settings allow never run
+    let parallel_row = adw::SpinRow::with_range(1.0, parallel.max(1) as f64, 1.0);
+    parallel_row.set_title("Parallel downloads");
+    parallel_row.set_subtitle("up to the number set in the settings");
+    parallel_row.set_value(parallel as f64);
+
+    let rate_row = adw::SpinRow::with_range(0.0, 1_000_000.0, 64.0);
+    rate_row.set_title("Bandwidth limit");
+    rate_row.set_subtitle("KiB/s, 0 for none");
+    rate_row.set_value(rate_limit as f64);
+
+    let list = gtk::ListBox::builder()
+        .selection_mode(gtk::SelectionMode::None)
+        .build();
+    list.add_css_class("boxed-list");
+    list.append(&parallel_row);
+    list.append(&rate_row);
+    d.set_extra_child(Some(&list));
+
+    d.add_response("cancel", "Cancel");
+    d.add_response("start", "Download");
+    d.set_response_appearance("start", adw::ResponseAppearance::Suggested);
+    d.set_close_response("cancel");
+    d.connect_response(None, move |_, r| {
+        if r == "start" {
+            on_start(parallel_row.value() as u32, rate_row.value() as u32);
+        }
+    });
+    d.present();
+}
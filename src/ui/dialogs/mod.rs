@@ -1,17 +1,23 @@
 mod about;
+mod edit_track;
 mod ffmpeg;
 mod folder;
+mod limits;
 mod logs;
 mod popup;
+mod resume;
 mod settings;
 mod sp_setup;
 mod ytdlp;
 mod ytdlp_update;
 
+pub use edit_track::edit_track;
 pub use ffmpeg::ffmpeg_missing;
 pub use folder::pick_folder;
+pub use limits::batch_limits;
 pub use logs::{log_viewer, append_log, LogHandle};
 pub use popup::show_popup;
+pub use resume::resume_queue;
 pub use settings::settings;
 pub use ytdlp::{ytdlp_missing, ytdlp_install_failed};
 pub use ytdlp_update::ytdlp_outdated;
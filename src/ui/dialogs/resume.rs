@@ -0,0 +1,23 @@
+use adw::prelude::*;
+
+pub fn resume_queue(
+    window: &adw::ApplicationWindow,
+    pending: usize,
+    on_resume: impl Fn() + 'static,
+    on_discard: impl Fn() + 'static,
+) {
+    let d = adw::MessageDialog::new(
+        Some(window),
+        Some("unfinished downloads"),
+        Some(&format!("{pending} downloads from the last session didn't finish. resume them?")),
+    );
+    d.add_response("discard", "Discard");
+    d.add_response("resume", "Resume");
+    d.set_response_appearance("discard", adw::ResponseAppearance::Destructive);
+    d.set_response_appearance("resume", adw::ResponseAppearance::Suggested);
+    d.set_close_response("resume");
+    d.connect_response(None, move |_, r| {
+        if r == "resume" { on_resume(); } else { on_discard(); }
+    });
+    d.present();
+}
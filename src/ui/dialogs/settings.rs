@@ -1,20 +1,50 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
 use adw::prelude::*;
 
-use crate::backend::spotify;
+use crate::backend::loudness::GainMode;
+use crate::backend::sanitize::NameProfile;
+use crate::backend::{lyrics, spotify, template};
+use crate::config::Settings;
+use crate::models::{format, AudioFormat, Track};
 use super::sp_setup::sp_setup_dialog;
 
 pub struct SettingsHandle {
     pub sp_row: adw::ActionRow,
     pub conn_btn: gtk::Button,
     pub disc_btn: gtk::Button,
+    pub cancel_btn: gtk::Button,
+}
+
+/// working copy of the settings, every edit is pushed back to the app
+#[derive(Clone)]
+struct CfgEdit {
+    cfg: Rc<RefCell<Settings>>,
+    on_change: Rc<dyn Fn(Settings)>,
+}
+
+impl CfgEdit {
+    fn get(&self) -> Settings {
+        self.cfg.borrow().clone()
+    }
+
+    fn set(&self, f: impl FnOnce(&mut Settings)) {
+        f(&mut self.cfg.borrow_mut());
+        (self.on_change)(self.get());
+    }
 }
 
 pub fn settings(
     window: &adw::ApplicationWindow,
     dl_dir: &str,
     on_dir: impl Fn() + 'static,
+    cfg: &Settings,
+    on_cfg: impl Fn(Settings) + 'static,
     spotify_name: Option<&str>,
+    spotify_connecting: bool,
     on_spotify_connect: impl Fn() + 'static,
+    on_spotify_cancel: impl Fn() + 'static,
     on_spotify_disconnect: impl Fn() + 'static,
     on_close: impl Fn() + 'static,
 ) -> SettingsHandle {
@@ -48,6 +78,11 @@ pub fn settings(
     let general_group = adw::PreferencesGroup::new();
     general_group.add(&dir_row);
 
+    let edit = CfgEdit {
+        cfg: Rc::new(RefCell::new(cfg.clone())),
+        on_change: Rc::new(on_cfg),
+    };
+
     let client_id = spotify::load_client_id().unwrap_or_default();
 
     let id_row = adw::ActionRow::builder()
@@ -62,19 +97,39 @@ pub fn settings(
 
     let win_ref = window.clone();
     let id_row_ref = id_row.clone();
+    let e = edit.clone();
     setup_btn.connect_clicked(move |_| {
         let row = id_row_ref.clone();
-        sp_setup_dialog(&win_ref, move |new_id| {
+        sp_setup_dialog(&win_ref, e.get().sp_port, move |new_id| {
             row.set_subtitle(&new_id);
         });
     });
     id_row.add_suffix(&setup_btn);
 
+    let secret_row = adw::PasswordEntryRow::builder()
+        .title("Client secret (public lookups without connecting)")
+        .text(spotify::load_client_secret().unwrap_or_default())
+        .show_apply_button(true)
+        .build();
+    secret_row.connect_apply(|r| spotify::save_client_secret(r.text().trim()));
+
     let connected = spotify_name.is_some();
 
+    let e = edit.clone();
+    let port_row = spin_row(
+        "Callback port",
+        "the redirect uri registered for your spotify app has to use it",
+        1024, 65535, cfg.sp_port as u32,
+        move |v| e.set(|c| c.sp_port = v as u16),
+    );
+
     let sp_row = adw::ActionRow::builder()
         .title("Account")
-        .subtitle(spotify_name.unwrap_or("not connected"))
+        .subtitle(match spotify_name {
+            Some(name) => name,
+            None if spotify_connecting => "connecting...",
+            None => "not connected",
+        })
         .build();
 
     let connect_btn = gtk::Button::builder()
@@ -84,7 +139,7 @@ pub fn settings(
         .build();
     connect_btn.add_css_class("suggested-action");
     let has_id = !client_id.is_empty();
-    connect_btn.set_sensitive(has_id);
+    connect_btn.set_sensitive(has_id && !spotify_connecting);
     if !has_id {
         connect_btn.set_tooltip_text(Some("set client id first"));
     }
@@ -96,13 +151,22 @@ pub fn settings(
         .build();
     disconnect_btn.add_css_class("destructive-action");
 
+    let cancel_btn = gtk::Button::builder()
+        .label("Cancel")
+        .valign(gtk::Align::Center)
+        .visible(spotify_connecting)
+        .build();
+    cancel_btn.connect_clicked(move |_| on_spotify_cancel());
+
     let sp_row_c = sp_row.clone();
     let disconnect_btn_c = disconnect_btn.clone();
+    let cancel_btn_c = cancel_btn.clone();
     connect_btn.connect_clicked(move |b| {
         on_spotify_connect();
         sp_row_c.set_subtitle("connecting...");
         b.set_sensitive(false);
         disconnect_btn_c.set_visible(false);
+        cancel_btn_c.set_visible(true);
     });
 
     let sp_row_d = sp_row.clone();
@@ -114,6 +178,7 @@ pub fn settings(
         connect_btn_d.set_sensitive(true);
     });
 
+    sp_row.add_suffix(&cancel_btn);
     sp_row.add_suffix(&connect_btn);
     sp_row.add_suffix(&disconnect_btn);
 
@@ -121,6 +186,8 @@ pub fn settings(
         .title("Spotify")
         .build();
     spotify_group.add(&id_row);
+    spotify_group.add(&secret_row);
+    spotify_group.add(&port_row);
     spotify_group.add(&sp_row);
 
     let about_btn = gtk::Button::builder()
@@ -136,6 +203,12 @@ pub fn settings(
 
     let page = adw::PreferencesPage::new();
     page.add(&general_group);
+    page.add(&audio_group(&edit));
+    page.add(&downloads_group(&edit));
+    page.add(&names_group(&edit));
+    page.add(&tags_group(&edit));
+    page.add(&covers_group(&edit));
+    page.add(&lyrics_group(&edit));
     page.add(&spotify_group);
 
     let content = gtk::Box::builder()
@@ -154,5 +227,333 @@ pub fn settings(
         sp_row,
         conn_btn: connect_btn,
         disc_btn: disconnect_btn,
+        cancel_btn,
+    }
+}
+
+fn audio_group(edit: &CfgEdit) -> adw::PreferencesGroup {
+    let cfg = edit.get();
+
+    let formats: Vec<&str> = AudioFormat::ALL.iter().map(|f| f.label()).collect();
+    let format_row = adw::ComboRow::builder()
+        .title("Format")
+        .model(&gtk::StringList::new(&formats))
+        .build();
+    format_row.set_selected(cfg.format.index());
+
+    let labels: Vec<String> = format::BITRATES.iter().map(|b| format::bitrate_label(*b)).collect();
+    let labels: Vec<&str> = labels.iter().map(String::as_str).collect();
+    let bitrate_row = adw::ComboRow::builder()
+        .title("Quality")
+        .model(&gtk::StringList::new(&labels))
+        .build();
+    bitrate_row.set_selected(format::BITRATES.iter().position(|b| *b == cfg.bitrate).unwrap_or(0) as u32);
+    bitrate_row.set_sensitive(!cfg.format.lossless());
+
+    let e = edit.clone();
+    let bitrate_ref = bitrate_row.clone();
+    format_row.connect_selected_notify(move |row| {
+        let fmt = AudioFormat::from_index(row.selected());
+        bitrate_ref.set_sensitive(!fmt.lossless());
+        e.set(|c| c.format = fmt);
+    });
+
+    let e = edit.clone();
+    bitrate_row.connect_selected_notify(move |row| {
+        let kbps = format::BITRATES.get(row.selected() as usize).copied().unwrap_or(0);
+        e.set(|c| c.bitrate = kbps);
+    });
+
+    let group = adw::PreferencesGroup::builder()
+        .title("Audio")
+        .build();
+    group.add(&format_row);
+    group.add(&bitrate_row);
+    group
+}
+
+fn downloads_group(edit: &CfgEdit) -> adw::PreferencesGroup {
+    let cfg = edit.get();
+    let group = adw::PreferencesGroup::builder()
+        .title("Downloads")
+        .build();
+
+    let e = edit.clone();
+    group.add(&spin_row("Parallel downloads", "applies to the running queue too", 1, 16, cfg.parallel,
+        move |n| e.set(|c| c.parallel = n)));
+    let e = edit.clone();
+    group.add(&spin_row("Bandwidth limit", "KiB/s split across downloads, 0 for none", 0, 1_000_000, cfg.rate_limit,
+        move |n| e.set(|c| c.rate_limit = n)));
+    let e = edit.clone();
+    group.add(&spin_row("Search candidates", "results compared before picking one", 1, 20, cfg.candidates,
+        move |n| e.set(|c| c.candidates = n)));
+    let e = edit.clone();
+    group.add(&spin_row("Attempts", "tries per track before it fails", 1, 10, cfg.attempts,
+        move |n| e.set(|c| c.attempts = n)));
+    let e = edit.clone();
+    group.add(&spin_row("Retry delay", "seconds, doubled after each retry", 0, 120, cfg.retry_delay,
+        move |n| e.set(|c| c.retry_delay = n)));
+
+    let suspend_row = adw::SwitchRow::builder()
+        .title("Suspend on pause")
+        .subtitle("stop running downloads too instead of letting them finish")
+        .active(cfg.suspend_on_pause)
+        .build();
+    let e = edit.clone();
+    suspend_row.connect_active_notify(move |r| {
+        let on = r.is_active();
+        e.set(|c| c.suspend_on_pause = on);
+    });
+    group.add(&suspend_row);
+
+    let skip_row = adw::SwitchRow::builder()
+        .title("Skip existing tracks")
+        .subtitle("by file name, tags in the target folder and download history")
+        .active(cfg.skip_existing)
+        .build();
+    let e = edit.clone();
+    skip_row.connect_active_notify(move |r| {
+        let on = r.is_active();
+        e.set(|c| c.skip_existing = on);
+    });
+    group.add(&skip_row);
+    group
+}
+
+fn spin_row(title: &str, subtitle: &str, min: u32, max: u32, value: u32, on_change: impl Fn(u32) + 'static) -> adw::SpinRow {
+    let row = adw::SpinRow::with_range(min as f64, max as f64, 1.0);
+    row.set_title(title);
+    row.set_subtitle(subtitle);
+    row.set_value(value as f64);
+    row.connect_value_notify(move |r| on_change(r.value() as u32));
+    row
+}
+
+fn names_group(edit: &CfgEdit) -> adw::PreferencesGroup {
+    let cfg = edit.get();
+    let group = adw::PreferencesGroup::builder()
+        .title("File names")
+        .description(format!(
+            "fields: {}. {{track:02}} pads, [...] is left out when a field in it is empty, / makes a folder",
+            template::FIELDS.iter().map(|f| format!("{{{f}}}")).collect::<Vec<_>>().join(" "),
+        ))
+        .build();
+
+    let e = edit.clone();
+    add_template_rows(&group, edit, "Singles", &cfg.tpl_single, sample(false, ""), move |t| e.set(|c| c.tpl_single = t));
+    let e = edit.clone();
+    add_template_rows(&group, edit, "Album tracks", &cfg.tpl_album, sample(true, ""), move |t| e.set(|c| c.tpl_album = t));
+    let e = edit.clone();
+    add_template_rows(&group, edit, "Playlist tracks", &cfg.tpl_playlist, sample(false, "Road Trip"), move |t| e.set(|c| c.tpl_playlist = t));
+
+    let profiles: Vec<&str> = NameProfile::ALL.iter().map(|p| p.label()).collect();
+    let profile_row = adw::ComboRow::builder()
+        .title("Target filesystem")
+        .subtitle("characters, reserved names and length limits to respect")
+        .model(&gtk::StringList::new(&profiles))
+        .build();
+    profile_row.set_selected(cfg.name_profile.index());
+    let e = edit.clone();
+    profile_row.connect_selected_notify(move |row| {
+        let profile = NameProfile::from_index(row.selected());
+        e.set(|c| c.name_profile = profile);
+    });
+    group.add(&profile_row);
+
+    let ascii_row = adw::SwitchRow::builder()
+        .title("ASCII only")
+        .subtitle("transliterate accents and other scripts, for players that can't show them")
+        .active(cfg.ascii_names)
+        .build();
+    let e = edit.clone();
+    ascii_row.connect_active_notify(move |r| {
+        let on = r.is_active();
+        e.set(|c| c.ascii_names = on);
+    });
+    group.add(&ascii_row);
+    group
+}
+
+fn tags_group(edit: &CfgEdit) -> adw::PreferencesGroup {
+    let cfg = edit.get();
+    let group = adw::PreferencesGroup::builder()
+        .title("Tags")
+        .build();
+
+    let labels: Vec<String> = JOINERS.iter().map(|j| format!("A{j}B")).collect();
+    let labels: Vec<&str> = labels.iter().map(String::as_str).collect();
+    let joiner_row = adw::ComboRow::builder()
+        .title("Artist separator")
+        .subtitle("for file names, and tags that hold a single artist")
+        .model(&gtk::StringList::new(&labels))
+        .build();
+    joiner_row.set_selected(JOINERS.iter().position(|j| *j == cfg.artist_joiner).unwrap_or(0) as u32);
+    let e = edit.clone();
+    joiner_row.connect_selected_notify(move |row| {
+        let joiner = JOINERS.get(row.selected() as usize).copied().unwrap_or(JOINERS[0]);
+        e.set(|c| c.artist_joiner = joiner.to_string());
+    });
+    group.add(&joiner_row);
+
+    let multi_row = adw::SwitchRow::builder()
+        .title("Separate artist values")
+        .subtitle("one artist tag per artist in mp3, flac and ogg, m4a always gets the joined form")
+        .active(cfg.multi_artist)
+        .build();
+    let e = edit.clone();
+    multi_row.connect_active_notify(move |r| {
+        let on = r.is_active();
+        e.set(|c| c.multi_artist = on);
+    });
+    group.add(&multi_row);
+
+    let modes: Vec<&str> = GainMode::ALL.iter().map(|m| m.label()).collect();
+    let gain_row = adw::ComboRow::builder()
+        .title("Loudness")
+        .subtitle("EBU R128 scan, applying re-encodes at the track gain and skips album gain")
+        .model(&gtk::StringList::new(&modes))
+        .build();
+    gain_row.set_selected(cfg.gain_mode.index());
+    let e = edit.clone();
+    gain_row.connect_selected_notify(move |row| {
+        let mode = GainMode::from_index(row.selected());
+        e.set(|c| c.gain_mode = mode);
+    });
+    group.add(&gain_row);
+    group
+}
+
+fn covers_group(edit: &CfgEdit) -> adw::PreferencesGroup {
+    let cfg = edit.get();
+    let group = adw::PreferencesGroup::builder()
+        .title("Cover art")
+        .build();
+
+    let e = edit.clone();
+    group.add(&spin_row("Max cover size", "px, larger covers are scaled down and saved as jpeg, 0 keeps them as is", 0, 3000, cfg.cover_max,
+        move |n| e.set(|c| c.cover_max = n)));
+    let e = edit.clone();
+    group.add(&spin_row("Cover cache", "MiB kept on disk, least recently used covers go first", 10, 5000, cfg.cover_cache_mb,
+        move |n| e.set(|c| c.cover_cache_mb = n)));
+
+    let folder_row = adw::SwitchRow::builder()
+        .title("Folder art")
+        .subtitle("cover.jpg and folder.jpg in album folders")
+        .active(cfg.folder_art)
+        .build();
+    let e = edit.clone();
+    folder_row.connect_active_notify(move |r| {
+        let on = r.is_active();
+        e.set(|c| c.folder_art = on);
+    });
+    group.add(&folder_row);
+    group
+}
+
+fn lyrics_group(edit: &CfgEdit) -> adw::PreferencesGroup {
+    let cfg = edit.get();
+    let group = adw::PreferencesGroup::builder()
+        .title("Lyrics")
+        .description("looked up after tagging, a download never fails over missing lyrics")
+        .build();
+
+    let embed_row = adw::SwitchRow::builder()
+        .title("Embed lyrics")
+        .subtitle("plain text in the file's lyrics tag")
+        .active(cfg.lyrics_embed)
+        .build();
+    let e = edit.clone();
+    embed_row.connect_active_notify(move |r| {
+        let on = r.is_active();
+        e.set(|c| c.lyrics_embed = on);
+    });
+    group.add(&embed_row);
+
+    let lrc_row = adw::SwitchRow::builder()
+        .title("Synced lyrics file")
+        .subtitle("time-synced .lrc next to the track, when available")
+        .active(cfg.lyrics_lrc)
+        .build();
+    let e = edit.clone();
+    lrc_row.connect_active_notify(move |r| {
+        let on = r.is_active();
+        e.set(|c| c.lyrics_lrc = on);
+    });
+    group.add(&lrc_row);
+
+    let url_row = adw::EntryRow::builder()
+        .title("Lyrics server (LRCLIB compatible)")
+        .text(&cfg.lyrics_url)
+        .build();
+    let e = edit.clone();
+    url_row.connect_changed(move |r| {
+        let url = r.text().trim().to_string();
+        let url = if url.is_empty() { lyrics::DEFAULT_URL.to_string() } else { url };
+        e.set(|c| c.lyrics_url = url);
+    });
+    group.add(&url_row);
+    group
+}
+
+const JOINERS: &[&str] = &["; ", " & ", ", ", " feat. "];
+
+/// entry plus a live preview, only valid templates are saved
+fn add_template_rows(
+    group: &adw::PreferencesGroup,
+    edit: &CfgEdit,
+    title: &str,
+    value: &str,
+    sample: Track,
+    on_change: impl Fn(String) + 'static,
+) {
+    let entry = adw::EntryRow::builder()
+        .title(title)
+        .text(value)
+        .build();
+    let preview = adw::ActionRow::builder()
+        .title("preview")
+        .subtitle(preview_text(value, &sample, &edit.get().artist_joiner))
+        .build();
+    preview.add_css_class("dim-label");
+
+    let preview_ref = preview.clone();
+    let edit = edit.clone();
+    entry.connect_changed(move |e| {
+        let tpl = e.text().to_string();
+        let joiner = edit.get().artist_joiner;
+        preview_ref.set_subtitle(&preview_text(&tpl, &sample, &joiner));
+        if template::render(&tpl, &sample, &joiner).is_ok() {
+            on_change(tpl);
+        }
+    });
+
+    group.add(&entry);
+    group.add(&preview);
+}
+
+fn preview_text(tpl: &str, sample: &Track, joiner: &str) -> String {
+    match template::render(tpl, sample, joiner) {
+        Ok(parts) => format!("{}.{{ext}}", parts.join("/")),
+        Err(e) => format!("invalid: {e}"),
+    }
+}
+
+fn sample(album: bool, playlist: &str) -> Track {
+    Track {
+        title: String::from("Paranoid Android"),
+        artist: String::from("Radiohead"),
+        artists: vec![String::from("Radiohead")],
+        album: String::from("OK Computer"),
+        duration: 387.0,
+        track_pos: Some(2),
+        album_artist: String::from("Radiohead"),
+        track_total: Some(12),
+        disc: Some(1),
+        release_date: String::from("1997-05-21"),
+        genres: vec![String::from("Alternative")],
+        label: String::from("Parlophone"),
+        is_album_track: album,
+        playlist: playlist.to_string(),
+        ..Track::default()
     }
 }
@@ -2,7 +2,7 @@ use adw::prelude::*;
 
 use crate::backend::spotify;
 
-pub fn sp_setup_dialog(window: &adw::ApplicationWindow, on_saved: impl Fn(String) + 'static) {
+pub fn sp_setup_dialog(window: &adw::ApplicationWindow, port: u16, on_saved: impl Fn(String) + 'static) {
     let d = adw::Window::builder()
         .title("Spotify Setup")
         .default_width(500)
@@ -14,12 +14,14 @@ pub fn sp_setup_dialog(window: &adw::ApplicationWindow, on_saved: impl Fn(String
     let header = adw::HeaderBar::new();
 
     let instructions = gtk::Label::builder()
-        .label(
+        .label(format!(
             "1. go to developer.spotify.com/dashboard\n\
              2. create an app (any name)\n\
-             3. set redirect uri to:\n   http://127.0.0.1:18492/callback\n\
-             4. copy the client id and paste it below"
-        )
+             3. set redirect uri to:\n   {}\n\
+             4. copy the client id and paste it below\n\n\
+             the port can be changed in settings, the uri above has to match it",
+            spotify::redirect_uri(port)
+        ))
         .wrap(true)
         .halign(gtk::Align::Start)
         .margin_start(16)
@@ -1,12 +1,43 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
 use relm4::prelude::*;
 
 use crate::backend;
+use crate::backend::loudness::GainMode;
+use crate::backend::queue::QueueEntry;
+use crate::models::progress::{Phase, Progress};
 use crate::models::{DlStatus, Track};
 use super::app::{App, Msg};
 use super::dialogs;
+use super::dl_row::DlRow;
 use super::result_row::ResultItem;
+use super::{search, sp};
+
+/// per-batch override of the parallel count and bandwidth limit
+#[derive(Debug, Clone, Default)]
+pub struct Batch {
+    parallel: Option<u32>,
+    rate_limit: Option<u32>,
+    sem: Option<Arc<tokio::sync::Semaphore>>,
+    /// download even if the track already exists
+    force: bool,
+}
+
+impl Batch {
+    pub fn new(parallel: u32, rate_limit: u32) -> Self {
+        let parallel = parallel.max(1);
+        Self {
+            parallel: Some(parallel),
+            rate_limit: Some(rate_limit),
+            sem: Some(Arc::new(tokio::sync::Semaphore::new(parallel as usize))),
+            force: false,
+        }
+    }
+}
 
-pub fn dl_selected(app: &mut App, sender: ComponentSender<App>) {
+pub fn dl_selected(app: &mut App, batch: Batch, sender: ComponentSender<App>) {
     let guard = app.results.guard();
     let mut tracks = Vec::new();
     let mut albums = Vec::new();
@@ -29,22 +60,23 @@ pub fn dl_selected(app: &mut App, sender: ComponentSender<App>) {
     app.busy = true;
     if !albums.is_empty() {
         app.status = format!("fetching {} albums", albums.len());
+        let tokens = sp::lookup_tokens(app);
         let s = sender.input_sender().clone();
         relm4::spawn(async move {
             let mut all = tracks;
             for album in &albums {
-                if let Ok(t) = backend::deezer::album_tracks(album).await {
+                if let Ok(t) = search::fetch_album(album, tokens.clone()).await {
                     all.extend(t);
                 }
             }
-            s.emit(Msg::DlStart(all));
+            s.emit(Msg::DlStart(all, batch));
         });
     } else {
-        sender.input(Msg::DlStart(tracks));
+        sender.input(Msg::DlStart(tracks, batch));
     }
 }
 
-pub fn dl_tracks(app: &mut App, tracks: Vec<Track>, sender: ComponentSender<App>) {
+pub fn dl_tracks(app: &mut App, tracks: Vec<Track>, batch: Batch, sender: ComponentSender<App>) {
     if tracks.is_empty() {
         app.status = String::from("no tracks found");
         return;
@@ -54,41 +86,166 @@ pub fn dl_tracks(app: &mut App, tracks: Vec<Track>, sender: ComponentSender<App>
     app.dl_started = Some(std::time::Instant::now());
     app.dl_total = tracks.len();
     app.dl_done = 0;
+    app.dl_files = 0;
+    app.dl_bytes = 0;
 
+    let status = waiting(app);
     let mut ids = Vec::new();
     let mut guard = app.downloads.guard();
     for track in &tracks {
         let id = app.next_dl_id;
         app.next_dl_id += 1;
         ids.push(id);
-        guard.push_front((id, track.clone()));
+        guard.push_front((id, track.clone(), app.dl_dir.clone()));
+        if let Some(row) = guard.get_mut(0) {
+            row.status = status.clone();
+            row.batch = batch.clone();
+        }
     }
     drop(guard);
 
-    let sem = std::sync::Arc::new(tokio::sync::Semaphore::new(3));
+    let dir = app.dl_dir.clone();
     for (track, id) in tracks.into_iter().zip(ids) {
-        let s = sender.input_sender().clone();
-        let dir = app.dl_dir.clone();
-        let sem = sem.clone();
-        relm4::spawn(async move {
-            let _permit = sem.acquire().await;
+        spawn_job(app, id, track, dir.clone(), &batch, sender.input_sender());
+    }
+    persist(app);
+}
+
+/// re-queue a failed or cancelled row with its original track,
+/// a skipped row is downloaded again regardless of the existing file
+pub fn dl_retry(app: &mut App, id: u64, sender: ComponentSender<App>) {
+    let status = waiting(app);
+    let job = row_mut(app, id, |row| {
+        if !matches!(row.status, DlStatus::Failed(_) | DlStatus::Cancelled | DlStatus::Skipped) { return None; }
+        let batch = Batch { force: row.status == DlStatus::Skipped, ..row.batch.clone() };
+        row.status = status;
+        row.progress = 0.0;
+        row.attempt = 1;
+        Some((row.track.clone(), row.dir.clone(), batch))
+    }).flatten();
+    let Some((track, dir, batch)) = job else { return };
+
+    app.busy = true;
+    // the row is already in the total, leaving the done count puts it back in the remaining
+    if app.dl_started.is_none() {
+        app.dl_started = Some(std::time::Instant::now());
+        app.dl_files = 0;
+        app.dl_bytes = 0;
+    }
+    spawn_job(app, id, track, dir, &batch, sender.input_sender());
+    tally(app);
+}
+
+fn spawn_job(app: &mut App, id: u64, track: Track, dir: PathBuf, batch: &Batch, s: &relm4::Sender<Msg>) {
+    let s = s.clone();
+    let mut cfg = app.settings.clone();
+    let (batch_parallel, batch_rate) = (batch.parallel, batch.rate_limit);
+    let batch_sem = batch.sem.clone();
+    let check_dupes = cfg.skip_existing && !batch.force;
+    let gate = app.gate.clone();
+    let job = relm4::spawn(async move {
+        let mut track = track;
+        let attempts = cfg.attempts.max(1);
+        let mut errors = Vec::new();
+        for attempt in 0..attempts {
+            if attempt > 0 {
+                s.emit(Msg::DlAttempt(id, attempt + 1, attempts));
+                let delay = cfg.retry_delay as u64 * (1 << (attempt - 1).min(6));
+                tokio::time::sleep(Duration::from_secs(delay)).await;
+            }
+            // taken per attempt so a retry in backoff doesn't hold a slot. a batch
+            // with its own limit takes one of its slots and a global one
+            let _batch_permit = match &batch_sem {
+                Some(sem) => match sem.acquire().await {
+                    Ok(p) => Some(p),
+                    Err(_) => return,
+                },
+                None => None,
+            };
+            let Some(_permit) = gate.acquire().await else { return };
+            // the rate is split as the job starts so settings changes reach queued jobs,
+            // over as many as both the batch and the global slots let run at once
+            let slots = gate.slots() as u32;
+            cfg.parallel = batch_parallel.map_or(slots, |n| n.min(slots));
+            cfg.rate_limit = batch_rate.unwrap_or(gate.rate_limit());
+            // under a slot so a big batch doesn't hit deezer or probe every folder at once.
+            // enriched first, the extra fields can show up in the target path
+            if attempt == 0 {
+                track = backend::deezer::enrich(track).await;
+            }
+            if attempt == 0 && check_dupes {
+                if let Some(dupe) = backend::dupes::find(&track, &dir, &cfg).await {
+                    s.emit(Msg::DlSkipped(id, dupe));
+                    return;
+                }
+            }
             let ps = s.clone();
-            let result = backend::ytdlp::download(&track, &dir, move |pct| {
-                ps.emit(Msg::DlProgress(id, pct));
+            let result = backend::ytdlp::download(&track, &dir, &cfg, attempt, &gate, move |p| {
+                ps.emit(Msg::DlProgress(id, p));
             })
             .await;
-            s.emit(Msg::DlDone(id, result));
-        });
-    }
+            match result {
+                Ok((path, log)) => {
+                    let log = if errors.is_empty() { log } else { format!("{}\n\n{log}", errors.join("\n\n")) };
+                    s.emit(Msg::DlDone(id, Ok((path, log))));
+                    return;
+                }
+                Err(e) if attempts > 1 => errors.push(format!("[attempt {}/{attempts}] {e}", attempt + 1)),
+                Err(e) => errors.push(e),
+            }
+        }
+        let summary = if attempts > 1 { format!("gave up after {attempts} attempts\n") } else { String::new() };
+        s.emit(Msg::DlDone(id, Err(format!("{summary}{}", errors.join("\n\n")))));
+    });
+    app.jobs.insert(id, job);
 }
 
-pub fn dl_progress(app: &mut App, id: u64, pct: f64) {
+pub fn dl_skipped(app: &mut App, id: u64, dupe: backend::dupes::Dupe, sender: ComponentSender<App>) {
+    app.jobs.remove(&id);
+    let label = row_mut(app, id, |row| {
+        // sent just before the job was aborted
+        if row.status == DlStatus::Cancelled { return None; }
+        row.status = DlStatus::Skipped;
+        // the existing file stands in for this track in the album gain
+        row.path = Some(dupe.path().to_path_buf());
+        Some(format!("{} - {}", row.track.artist, row.track.title))
+    }).flatten();
+    let Some(label) = label else { return };
+    push_log(app, format!("=== skipped: {label} ===\n{}", dupe.describe()));
+    album_gain(app, id, sender);
+    tally(app);
+}
+
+pub fn dl_attempt(app: &mut App, id: u64, attempt: u32, attempts: u32) {
+    let status = waiting(app);
+    row_mut(app, id, |row| {
+        if row.status == DlStatus::Cancelled { return; }
+        row.attempt = attempt;
+        row.attempts = attempts;
+        row.status = status;
+        row.progress = 0.0;
+        row.phase = Phase::default();
+        row.bytes_done = 0;
+        row.bytes_total = 0;
+    });
+}
+
+pub fn dl_progress(app: &mut App, id: u64, p: Progress) {
     let mut guard = app.downloads.guard();
     for i in 0..guard.len() {
         if guard.get(i).map_or(false, |r| r.id == id) {
             if let Some(row) = guard.get_mut(i) {
-                row.progress = pct;
-                row.status = DlStatus::Active(pct);
+                if row.status == DlStatus::Cancelled { break; }
+                row.progress = p.pct;
+                row.status = DlStatus::Active(p.pct);
+                row.phase = p.phase;
+                // later phases keep the byte counts for the eta
+                if p.phase == Phase::Downloading {
+                    row.bytes_done = p.done;
+                    row.bytes_total = p.total;
+                }
+                row.speed = p.speed;
+                row.eta = p.eta;
             }
             break;
         }
@@ -97,17 +254,25 @@ pub fn dl_progress(app: &mut App, id: u64, pct: f64) {
     update_eta(app);
 }
 
-pub fn dl_done(app: &mut App, id: u64, result: Result<String, String>) {
+pub fn dl_done(app: &mut App, id: u64, result: Result<(PathBuf, String), String>, sender: ComponentSender<App>) {
+    app.jobs.remove(&id);
     let mut guard = app.downloads.guard();
     let mut log_entry = None;
+    let mut album_track = false;
     for i in 0..guard.len() {
         if guard.get(i).map_or(false, |r| r.id == id) {
             if let Some(row) = guard.get_mut(i) {
+                // finished in the same tick it was cancelled
+                if row.status == DlStatus::Cancelled { break; }
                 let label = format!("{} - {}", row.track.artist, row.track.title);
+                album_track = row.track.is_album_track;
                 match &result {
-                    Ok(log) => {
+                    Ok((path, log)) => {
                         row.progress = 100.0;
                         row.status = DlStatus::Done;
+                        app.dl_files += 1;
+                        app.dl_bytes += row.bytes_total.max(row.bytes_done);
+                        row.path = Some(path.clone());
                         log_entry = Some(format!("=== {label} ===\n{log}"));
                     }
                     Err(e) => {
@@ -119,23 +284,224 @@ pub fn dl_done(app: &mut App, id: u64, result: Result<String, String>) {
             break;
         }
     }
-    let total = guard.len();
-    let done = (0..total)
-        .filter(|i| {
-            guard.get(*i).map_or(false, |d| {
-                matches!(d.status, DlStatus::Done | DlStatus::Failed(_))
-            })
-        })
-        .count();
     drop(guard);
 
     if let Some(entry) = log_entry {
-        if let Some(handle) = &app.log_handle {
-            dialogs::append_log(handle, &entry);
+        push_log(app, entry);
+    }
+    if album_track {
+        album_gain(app, id, sender);
+    }
+    tally(app);
+}
+
+/// once every queued track of `id`'s album is done or skipped as existing, tag
+/// them all with the album gain. cancelled tracks don't count, a failed one
+/// holds it back until its retry succeeds
+fn album_gain(app: &mut App, id: u64, sender: ComponentSender<App>) {
+    if app.settings.gain_mode != GainMode::Tags { return; }
+    let guard = app.downloads.guard();
+    let rows: Vec<&DlRow> = (0..guard.len()).filter_map(|i| guard.get(i)).collect();
+    let key = |r: &DlRow| (r.dir.clone(), r.track.album_artist.clone(), r.track.album.clone());
+    let Some(want) = rows.iter().find(|r| r.id == id && r.track.is_album_track).map(|r| key(r)) else { return };
+    let album: Vec<&DlRow> = rows.into_iter()
+        .filter(|r| r.track.is_album_track && r.status != DlStatus::Cancelled && key(r) == want)
+        .collect();
+    // still running, this gets another look when they finish
+    if album.iter().any(|r| !r.status.finished()) { return; }
+    let failed = album.iter().filter(|r| matches!(r.status, DlStatus::Failed(_))).count();
+    let files: Vec<(PathBuf, f64)> = album.iter()
+        .filter_map(|r| Some((r.path.clone()?, r.track.duration)))
+        .collect();
+    let missing = album.len() - failed - files.len();
+    drop(guard);
+
+    let name = want.2;
+    if failed > 0 || missing > 0 || files.is_empty() {
+        let why = if failed > 0 { format!("{failed} tracks failed") } else { format!("{missing} tracks have no file") };
+        push_log(app, format!("=== {name} ===\n[gain] album gain skipped, {why}"));
+        return;
+    }
+
+    let s = sender.input_sender().clone();
+    relm4::spawn(async move {
+        let entry = match backend::loudness::tag_album(files).await {
+            Ok(msg) => format!("=== {name} ===\n[gain] {msg}"),
+            Err(e) => format!("=== {name} ===\n[gain] album: {e}"),
+        };
+        s.emit(Msg::DlLog(entry));
+    });
+}
+
+/// aborting the task drops the download future, which kills the child
+/// processes and removes the partial files. a restored row has no job yet
+pub fn dl_cancel(app: &mut App, id: u64, sender: ComponentSender<App>) {
+    if let Some(job) = app.jobs.remove(&id) {
+        job.abort();
+    }
+    let label = row_mut(app, id, |row| {
+        if row.status.finished() { return None; }
+        row.status = DlStatus::Cancelled;
+        Some(format!("{} - {}", row.track.artist, row.track.title))
+    }).flatten();
+    let Some(label) = label else { return };
+    push_log(app, format!("=== cancelled: {label} ==="));
+    // the rest of its album may be waiting on it
+    album_gain(app, id, sender);
+    tally(app);
+}
+
+pub fn dl_cancel_all(app: &mut App, sender: ComponentSender<App>) {
+    let ids: Vec<u64> = app.jobs.keys().copied().collect();
+    for id in ids {
+        dl_cancel(app, id, sender.clone());
+    }
+}
+
+/// load the queue file from the last session into the downloads panel
+pub fn restore(app: &mut App, root: &adw::ApplicationWindow, sender: ComponentSender<App>) {
+    let entries = backend::queue::load();
+    if entries.is_empty() { return; }
+
+    let mut pending = 0;
+    let mut guard = app.downloads.guard();
+    for entry in entries {
+        // anything that was running got killed with the app
+        let status = match entry.status {
+            DlStatus::Failed(e) => DlStatus::Failed(e),
+            _ => { pending += 1; DlStatus::Queued }
+        };
+        backend::queue::clean_partials(&entry.dir, &entry.track, &app.settings);
+        let id = app.next_dl_id;
+        app.next_dl_id += 1;
+        app.restored.push(id);
+        guard.push_back((id, entry.track, entry.dir));
+        if let Some(row) = guard.get_mut(guard.len() - 1) {
+            row.status = status;
         }
-        app.logs.push(entry);
     }
+    drop(guard);
+
+    if pending == 0 {
+        app.status = format!("{} failed downloads from last session", app.restored.len());
+        return;
+    }
+    let (s1, s2) = (sender.input_sender().clone(), sender.input_sender().clone());
+    dialogs::resume_queue(root, pending, move || s1.emit(Msg::QueueResume), move || s2.emit(Msg::QueueDiscard));
+}
+
+pub fn resume(app: &mut App, sender: ComponentSender<App>) {
+    let ids = std::mem::take(&mut app.restored);
+    let mut queued = Vec::new();
+    let guard = app.downloads.guard();
+    for i in 0..guard.len() {
+        let Some(row) = guard.get(i) else { continue };
+        if ids.contains(&row.id) && row.status == DlStatus::Queued {
+            queued.push((row.id, row.track.clone(), row.dir.clone()));
+        }
+    }
+    drop(guard);
+    if queued.is_empty() { return; }
+
+    app.busy = true;
+    app.status = format!("resuming {} tracks", queued.len());
+    app.dl_started = Some(std::time::Instant::now());
+    app.dl_total = queued.len();
+    app.dl_done = 0;
+    app.dl_files = 0;
+    app.dl_bytes = 0;
+    for (id, track, dir) in queued {
+        spawn_job(app, id, track, dir, &Batch::default(), sender.input_sender());
+    }
+}
+
+pub fn discard(app: &mut App) {
+    let ids = std::mem::take(&mut app.restored);
+    let mut guard = app.downloads.guard();
+    for i in (0..guard.len()).rev() {
+        if guard.get(i).map_or(false, |r| ids.contains(&r.id)) {
+            guard.remove(i);
+        }
+    }
+    drop(guard);
+    app.status = String::from("queue discarded");
+    persist(app);
+}
+
+/// write every row that still needs work to the queue file
+fn persist(app: &mut App) {
+    let guard = app.downloads.guard();
+    let entries: Vec<QueueEntry> = (0..guard.len())
+        .filter_map(|i| guard.get(i))
+        .filter(|r| !matches!(r.status, DlStatus::Done | DlStatus::Cancelled | DlStatus::Skipped))
+        .map(|r| QueueEntry { track: r.track.clone(), status: r.status.clone(), dir: r.dir.clone() })
+        .collect();
+    drop(guard);
+    backend::queue::save_later(entries);
+}
+
+pub fn toggle_pause(app: &mut App) {
+    let mut guard = app.downloads.guard();
+    match app.paused_at.take() {
+        Some(at) => {
+            app.gate.resume();
+            // push the start forward so the paused time doesn't count toward the eta
+            if let Some(started) = app.dl_started.as_mut() {
+                *started += at.elapsed();
+            }
+            for i in 0..guard.len() {
+                let Some(row) = guard.get_mut(i) else { continue };
+                if row.status == DlStatus::Paused {
+                    row.status = if row.progress > 0.0 { DlStatus::Active(row.progress) } else { DlStatus::Queued };
+                }
+            }
+            app.status = String::from("resumed");
+        }
+        None => {
+            let suspend = app.settings.suspend_on_pause;
+            app.gate.pause(suspend);
+            app.paused_at = Some(std::time::Instant::now());
+            for i in 0..guard.len() {
+                let Some(row) = guard.get_mut(i) else { continue };
+                match row.status {
+                    DlStatus::Queued => row.status = DlStatus::Paused,
+                    DlStatus::Active(_) if suspend => row.status = DlStatus::Paused,
+                    _ => {}
+                }
+            }
+            app.status = String::from("paused");
+        }
+    }
+}
+
+fn waiting(app: &App) -> DlStatus {
+    if app.paused_at.is_some() { DlStatus::Paused } else { DlStatus::Queued }
+}
+
+fn row_mut<R>(app: &mut App, id: u64, f: impl FnOnce(&mut DlRow) -> R) -> Option<R> {
+    let mut guard = app.downloads.guard();
+    let i = (0..guard.len()).find(|i| guard.get(*i).map_or(false, |r| r.id == id))?;
+    guard.get_mut(i).map(f)
+}
+
+pub fn push_log(app: &mut App, entry: String) {
+    if let Some(handle) = &app.log_handle {
+        dialogs::append_log(handle, &entry);
+    }
+    app.logs.push(entry);
+}
+
+fn tally(app: &mut App) {
+    let guard = app.downloads.guard();
+    let total = guard.len();
+    let done = (0..total)
+        .filter(|i| guard.get(*i).map_or(false, |d| d.status.finished()))
+        .count();
+    drop(guard);
+
+    app.dl_total = total;
     app.dl_done = done;
+    persist(app);
     if done == total {
         app.busy = false;
         app.status = format!("done ({total} tracks)");
@@ -148,6 +514,8 @@ pub fn dl_done(app: &mut App, id: u64, result: Result<String, String>) {
 }
 
 fn update_eta(app: &mut App) {
+    // frozen while paused
+    if app.paused_at.is_some() { return; }
     let started = match app.dl_started {
         Some(t) => t,
         None => {
@@ -156,13 +524,43 @@ fn update_eta(app: &mut App) {
         }
     };
     let remaining = app.dl_total.saturating_sub(app.dl_done);
-    if remaining == 0 || app.dl_done == 0 {
+    // only this run's downloads, earlier runs and cancelled or skipped rows
+    // would skew the per-track time and size
+    let completed = app.dl_files;
+    if remaining == 0 {
         app.eta = String::new();
         return;
     }
+
+    let guard = app.downloads.guard();
+    let (mut running, mut sized, mut got, mut left, mut sizes) = (0usize, 0usize, 0u64, 0u64, 0u64);
+    for r in (0..guard.len()).filter_map(|i| guard.get(i)) {
+        if !matches!(r.status, DlStatus::Active(_)) { continue; }
+        running += 1;
+        if r.bytes_total > 0 {
+            sized += 1;
+            got += r.bytes_done;
+            left += r.bytes_total.saturating_sub(r.bytes_done);
+            sizes += r.bytes_total;
+        }
+    }
+    drop(guard);
+
     let elapsed = started.elapsed().as_secs_f64();
-    let per_track = elapsed / app.dl_done as f64;
-    let secs_left = (per_track * remaining as f64) as u64;
+    let downloaded = app.dl_bytes + got;
+    let secs_left = if downloaded > 0 && completed + sized > 0 {
+        // bytes per wall clock second, so searching and tagging count too.
+        // tracks that haven't started are assumed to be average sized
+        let rate = downloaded as f64 / elapsed.max(1.0);
+        let avg = (app.dl_bytes + sizes) as f64 / (completed + sized) as f64;
+        let waiting = remaining.saturating_sub(running) as f64;
+        ((left as f64 + waiting * avg) / rate) as u64
+    } else if completed > 0 {
+        (elapsed / completed as f64 * remaining as f64) as u64
+    } else {
+        app.eta = String::new();
+        return;
+    };
     let m = secs_left / 60;
     let s = secs_left % 60;
     app.eta = format!("eta {m:02}:{s:02}");
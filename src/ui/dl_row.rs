@@ -1,24 +1,55 @@
+use std::path::PathBuf;
+
 use adw::prelude::*;
+use gtk::gdk;
 use relm4::prelude::*;
 
+use crate::backend::covers;
+use crate::models::progress::{self, Phase};
 use crate::models::{DlStatus, Track};
+use super::dl::Batch;
+use super::thumbs;
 
 pub struct DlRow {
     pub id: u64,
     pub track: Track,
+    pub dir: PathBuf,
     pub status: DlStatus,
     pub progress: f64,
+    pub attempt: u32,
+    pub attempts: u32,
+    pub phase: Phase,
+    pub bytes_done: u64,
+    /// 0 until yt-dlp knows the size
+    pub bytes_total: u64,
+    pub speed: f64,
+    pub eta: Option<u64>,
+    /// where the finished file ended up
+    pub path: Option<PathBuf>,
+    /// the limits it was queued with, a retry keeps them
+    pub batch: Batch,
+    thumb: Option<gdk::Texture>,
 }
 
 #[derive(Debug)]
-pub enum DlRowMsg {}
+pub enum DlRowMsg {
+    Cancel,
+    Retry,
+}
+
+#[derive(Debug)]
+pub enum DlRowOutput {
+    Cancel(u64),
+    Retry(u64),
+}
 
 #[relm4::factory(pub)]
 impl FactoryComponent for DlRow {
-    type Init = (u64, Track);
+    type Init = (u64, Track, PathBuf);
     type Input = DlRowMsg;
-    type Output = ();
-    type CommandOutput = ();
+    type Output = DlRowOutput;
+    /// thumbnail bytes
+    type CommandOutput = Option<Vec<u8>>;
     type ParentWidget = gtk::ListBox;
 
     view! {
@@ -27,6 +58,21 @@ impl FactoryComponent for DlRow {
             set_spacing: 12,
             set_margin_all: 8,
 
+            gtk::Image {
+                set_icon_name: Some("audio-x-generic-symbolic"),
+                set_pixel_size: 32,
+                #[watch]
+                set_visible: self.thumb.is_none(),
+            },
+
+            gtk::Image {
+                set_pixel_size: 32,
+                #[watch]
+                set_visible: self.thumb.is_some(),
+                #[watch]
+                set_paintable: self.thumb.as_ref(),
+            },
+
             gtk::Box {
                 set_orientation: gtk::Orientation::Vertical,
                 set_spacing: 4,
@@ -39,7 +85,7 @@ impl FactoryComponent for DlRow {
                     add_css_class: "heading",
                 },
                 gtk::Label {
-                    set_label: &self.track.artist,
+                    set_label: &self.track.artists_joined(", "),
                     set_halign: gtk::Align::Start,
                     set_ellipsize: gtk::pango::EllipsizeMode::End,
                     add_css_class: "dim-label",
@@ -55,11 +101,16 @@ impl FactoryComponent for DlRow {
 
             gtk::Label {
                 #[watch]
-                set_label: &match &self.status {
-                    DlStatus::Queued => String::from("queued"),
-                    DlStatus::Active(p) => format!("{p:.0}%"),
-                    DlStatus::Done => String::from("done"),
-                    DlStatus::Failed(e) => format!("fail: {e}"),
+                set_label: &match (&self.status, self.attempt > 1) {
+                    (DlStatus::Queued, false) => String::from("queued"),
+                    (DlStatus::Queued, true) => format!("retry {}/{}", self.attempt, self.attempts),
+                    (DlStatus::Active(_), false) => self.activity(),
+                    (DlStatus::Active(_), true) => format!("retry {}/{} · {}", self.attempt, self.attempts, self.activity()),
+                    (DlStatus::Paused, _) => String::from("paused"),
+                    (DlStatus::Done, _) => String::from("done"),
+                    (DlStatus::Failed(e), _) => format!("fail: {}", e.lines().next().unwrap_or_default()),
+                    (DlStatus::Cancelled, _) => String::from("cancelled"),
+                    (DlStatus::Skipped, _) => String::from("skipped (exists)"),
                 },
                 #[watch]
                 add_css_class: match &self.status {
@@ -71,17 +122,82 @@ impl FactoryComponent for DlRow {
                 set_halign: gtk::Align::End,
                 set_valign: gtk::Align::Center,
             },
+
+            gtk::Button {
+                set_icon_name: "process-stop-symbolic",
+                add_css_class: "flat",
+                set_tooltip_text: Some("cancel"),
+                set_valign: gtk::Align::Center,
+                #[watch]
+                set_visible: !self.status.finished(),
+                connect_clicked => DlRowMsg::Cancel,
+            },
+
+            gtk::Button {
+                set_icon_name: "view-refresh-symbolic",
+                add_css_class: "flat",
+                #[watch]
+                set_tooltip_text: Some(if self.status == DlStatus::Skipped { "download anyway" } else { "retry" }),
+                set_valign: gtk::Align::Center,
+                #[watch]
+                set_visible: matches!(self.status, DlStatus::Failed(_) | DlStatus::Cancelled | DlStatus::Skipped),
+                connect_clicked => DlRowMsg::Retry,
+            },
         }
     }
 
-    fn init_model(init: Self::Init, _index: &DynamicIndex, _sender: FactorySender<Self>) -> Self {
+    fn init_model(init: Self::Init, _index: &DynamicIndex, sender: FactorySender<Self>) -> Self {
+        let url = init.1.thumb_url.clone();
+        let thumb = thumbs::get(&url);
+        if thumb.is_none() && !url.is_empty() {
+            sender.oneshot_command(async move { covers::thumb(&url).await });
+        }
         Self {
             id: init.0,
             track: init.1,
+            dir: init.2,
             status: DlStatus::Queued,
             progress: 0.0,
+            attempt: 1,
+            attempts: 1,
+            phase: Phase::default(),
+            bytes_done: 0,
+            bytes_total: 0,
+            speed: 0.0,
+            eta: None,
+            path: None,
+            batch: Batch::default(),
+            thumb,
+        }
+    }
+
+    fn update_cmd(&mut self, data: Self::CommandOutput, _sender: FactorySender<Self>) {
+        if let Some(data) = data {
+            self.thumb = thumbs::insert(&self.track.thumb_url, data);
         }
     }
 
-    fn update(&mut self, _msg: Self::Input, _sender: FactorySender<Self>) {}
+    fn update(&mut self, msg: Self::Input, sender: FactorySender<Self>) {
+        match msg {
+            DlRowMsg::Cancel => { let _ = sender.output(DlRowOutput::Cancel(self.id)); }
+            DlRowMsg::Retry => { let _ = sender.output(DlRowOutput::Retry(self.id)); }
+        }
+    }
+}
+
+impl DlRow {
+    /// "42% · 1.8 MiB/s · 0:12" while downloading, otherwise the phase
+    fn activity(&self) -> String {
+        if self.phase != Phase::Downloading {
+            return self.phase.label().to_string();
+        }
+        let mut parts = vec![format!("{:.0}%", self.progress)];
+        if self.speed > 0.0 {
+            parts.push(format!("{}/s", progress::fmt_bytes(self.speed)));
+        }
+        if let Some(eta) = self.eta {
+            parts.push(progress::fmt_secs(eta));
+        }
+        parts.join(" · ")
+    }
 }
@@ -84,23 +84,45 @@ pub fn handle(app: &mut App, msg: Msg, sender: ComponentSender<App>, root: &adw:
         Msg::AlbumTracks(Err(e)) => { app.busy = false; app.status = format!("err: {e}"); }
         Msg::SelectAll => search::select_all(app, true),
         Msg::DeselectAll => search::select_all(app, false),
+        Msg::EditResult(i) => search::edit_result(app, i, root, sender),
+        Msg::ResultEdited(i, track) => search::result_edited(app, i, track),
 
-        Msg::DlSelected => dl::dl_selected(app, sender),
-        Msg::DlStart(tracks) => dl::dl_tracks(app, tracks, sender),
-        Msg::DlProgress(id, pct) => dl::dl_progress(app, id, pct),
-        Msg::DlDone(id, result) => dl::dl_done(app, id, result),
+        Msg::DlSelected(batch) => dl::dl_selected(app, batch, sender),
+        Msg::DlSelectedLimits => {
+            let s = sender.input_sender().clone();
+            dialogs::batch_limits(root, app.settings.parallel, app.settings.rate_limit, move |parallel, rate_limit| {
+                s.emit(Msg::DlSelected(dl::Batch::new(parallel, rate_limit)));
+            });
+        }
+        Msg::DlStart(tracks, batch) => dl::dl_tracks(app, tracks, batch, sender),
+        Msg::DlProgress(id, p) => dl::dl_progress(app, id, p),
+        Msg::DlDone(id, result) => dl::dl_done(app, id, result, sender),
+        Msg::DlLog(entry) => dl::push_log(app, entry),
+        Msg::DlAttempt(id, n, total) => dl::dl_attempt(app, id, n, total),
+        Msg::DlSkipped(id, dupe) => dl::dl_skipped(app, id, dupe, sender),
+        Msg::DlRetry(id) => dl::dl_retry(app, id, sender),
+        Msg::DlCancel(id) => dl::dl_cancel(app, id, sender),
+        Msg::DlCancelAll => dl::dl_cancel_all(app, sender),
+        Msg::DlTogglePause => dl::toggle_pause(app),
+        Msg::QueueRestore => dl::restore(app, root, sender),
+        Msg::QueueResume => dl::resume(app, sender),
+        Msg::QueueDiscard => dl::discard(app),
 
         Msg::SpConnect => sp::connect(app, sender),
         Msg::SpAuth(Ok(tokens)) => sp::auth_done(app, tokens, root),
-        Msg::SpAuth(Err(e)) => { app.busy = false; app.status = format!("spotify err: {e}"); }
+        Msg::SpAuth(Err(e)) => sp::auth_failed(app, e),
+        Msg::SpCancel => sp::cancel(app),
         Msg::SpDisconnect => sp::disconnect(app, root),
         Msg::SpLibrary => sp::load_library(app, sender),
         Msg::SpLibRes(Ok(playlists)) => sp::library_loaded(app, playlists),
         Msg::SpLibRes(Err(e)) => { app.busy = false; app.status = format!("spotify err: {e}"); }
         Msg::SpPlaylist(id, name) => sp::load_playlist(app, id, name, sender),
         Msg::SpLiked => sp::load_liked(app, sender),
+        Msg::SpSection(sec) => sp::load_section(app, sec, sender),
         Msg::SpTracks(Ok(tracks)) => sp::tracks_loaded(app, tracks),
         Msg::SpTracks(Err(e)) => { app.busy = false; app.status = format!("spotify err: {e}"); }
+        Msg::SpArtists(Ok(artists)) => sp::artists_loaded(app, artists),
+        Msg::SpArtists(Err(e)) => { app.busy = false; app.status = format!("spotify err: {e}"); }
 
         Msg::SetDlDir => {
             let s = sender.input_sender().clone();
@@ -111,27 +133,39 @@ pub fn handle(app: &mut App, msg: Msg, sender: ComponentSender<App>, root: &adw:
             let text = if app.logs.is_empty() { String::from("no logs yet") } else { app.logs.join("\n\n") };
             app.log_handle = Some(dialogs::log_viewer(root, &text));
         }
+        Msg::SettingsChanged(cfg) => {
+            crate::config::save_settings(&cfg);
+            app.gate.set_slots(cfg.parallel as usize);
+            app.gate.set_rate_limit(cfg.rate_limit);
+            app.settings = cfg;
+        }
         Msg::SettingsDone => {
             app.sp_row = None;
             app.sp_conn_btn = None;
             app.sp_disc_btn = None;
+            app.sp_cancel_btn = None;
         }
         Msg::ShowSettings => {
             let name = app.sp_tokens.as_ref().map(|t| t.display_name.clone());
             let s = sender.input_sender();
-            let (s1, s2, s3, s4) = (s.clone(), s.clone(), s.clone(), s.clone());
+            let (s1, s2, s3, s4, s5, s6) = (s.clone(), s.clone(), s.clone(), s.clone(), s.clone(), s.clone());
             let handle = dialogs::settings(
                 root,
                 &app.dl_dir.display().to_string(),
                 move || s1.emit(Msg::SetDlDir),
+                &app.settings,
+                move |cfg| s5.emit(Msg::SettingsChanged(cfg)),
                 name.as_deref(),
+                app.sp_auth.is_some(),
                 move || s2.emit(Msg::SpConnect),
+                move || s6.emit(Msg::SpCancel),
                 move || s3.emit(Msg::SpDisconnect),
                 move || s4.emit(Msg::SettingsDone),
             );
             app.sp_row = Some(handle.sp_row);
             app.sp_conn_btn = Some(handle.conn_btn);
             app.sp_disc_btn = Some(handle.disc_btn);
+            app.sp_cancel_btn = Some(handle.cancel_btn);
         }
     }
 }
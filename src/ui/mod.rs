@@ -6,5 +6,6 @@ mod handlers;
 mod result_row;
 mod search;
 mod sp;
+mod thumbs;
 
 pub use app::App;
@@ -1,8 +1,10 @@
 use adw::prelude::*;
+use gtk::gdk;
 use relm4::prelude::*;
 
-use crate::backend::spotify;
+use crate::backend::{covers, spotify};
 use crate::models::{Album, Artist, Track};
+use super::thumbs;
 
 #[derive(Debug, Clone)]
 pub enum ResultItem {
@@ -11,17 +13,31 @@ pub enum ResultItem {
     Track(Track),
     SpotifyPlaylist(spotify::Playlist),
     SpotifyLiked,
+    SpotifySection(spotify::Section),
+}
+
+impl ResultItem {
+    fn thumb_url(&self) -> &str {
+        match self {
+            Self::Album(a) => &a.thumb_url,
+            Self::Track(t) => &t.thumb_url,
+            _ => "",
+        }
+    }
 }
 
 pub struct ResultRow {
     pub item: ResultItem,
     pub selected: bool,
+    thumb: Option<gdk::Texture>,
+    index: DynamicIndex,
 }
 
 #[derive(Debug)]
 pub enum ResultRowMsg {
     Toggle,
     Browse,
+    Edit,
 }
 
 #[derive(Debug)]
@@ -30,6 +46,8 @@ pub enum ResultRowOutput {
     Artist(Artist),
     SpotifyPlaylist(String, String),
     SpotifyLiked,
+    SpotifySection(spotify::Section),
+    Edit(DynamicIndex),
 }
 
 #[relm4::factory(pub)]
@@ -37,7 +55,8 @@ impl FactoryComponent for ResultRow {
     type Init = ResultItem;
     type Input = ResultRowMsg;
     type Output = ResultRowOutput;
-    type CommandOutput = ();
+    /// thumbnail bytes
+    type CommandOutput = Option<Vec<u8>>;
     type ParentWidget = gtk::ListBox;
 
     view! {
@@ -61,8 +80,24 @@ impl FactoryComponent for ResultRow {
                     ResultItem::Track(_) => "audio-x-generic-symbolic",
                     ResultItem::SpotifyPlaylist(_) => "view-list-bullet-symbolic",
                     ResultItem::SpotifyLiked => "starred-symbolic",
+                    ResultItem::SpotifySection(sec) => match sec {
+                        spotify::Section::SavedAlbums => "media-optical-cd-audio-symbolic",
+                        spotify::Section::FollowedArtists => "avatar-default-symbolic",
+                        spotify::Section::Top(_) => "emblem-favorite-symbolic",
+                        spotify::Section::Recent => "document-open-recent-symbolic",
+                    },
                 }),
                 set_pixel_size: 32,
+                #[watch]
+                set_visible: self.thumb.is_none(),
+            },
+
+            gtk::Image {
+                set_pixel_size: 32,
+                #[watch]
+                set_visible: self.thumb.is_some(),
+                #[watch]
+                set_paintable: self.thumb.as_ref(),
             },
 
             gtk::Box {
@@ -71,6 +106,7 @@ impl FactoryComponent for ResultRow {
                 set_hexpand: true,
 
                 gtk::Label {
+                    #[watch]
                     set_label: &match &self.item {
                         ResultItem::Artist(a) => a.name.clone(),
                         ResultItem::Album(a) => a.title.clone(),
@@ -80,6 +116,7 @@ impl FactoryComponent for ResultRow {
                         },
                         ResultItem::SpotifyPlaylist(p) => p.name.clone(),
                         ResultItem::SpotifyLiked => String::from("Liked Songs"),
+                        ResultItem::SpotifySection(sec) => sec.label().to_string(),
                     },
                     set_halign: gtk::Align::Start,
                     set_ellipsize: gtk::pango::EllipsizeMode::End,
@@ -91,12 +128,17 @@ impl FactoryComponent for ResultRow {
                     set_spacing: 8,
 
                     gtk::Label {
+                        #[watch]
                         set_label: &match &self.item {
+                            // spotify doesn't count an artist's albums
+                            ResultItem::Artist(a) if !a.sp_id.is_empty() => String::from("spotify"),
                             ResultItem::Artist(a) => format!("{} albums", a.nb_album),
                             ResultItem::Album(a) => a.artist.clone(),
-                            ResultItem::Track(t) => t.artist.clone(),
+                            ResultItem::Track(t) => t.artists_joined(", "),
                             ResultItem::SpotifyPlaylist(p) => format!("{} tracks", p.nb_tracks),
                             ResultItem::SpotifyLiked => String::from("spotify"),
+                            ResultItem::SpotifySection(spotify::Section::Top(term)) => term.label().to_string(),
+                            ResultItem::SpotifySection(_) => String::from("spotify"),
                         },
                         set_halign: gtk::Align::Start,
                         set_ellipsize: gtk::pango::EllipsizeMode::End,
@@ -129,6 +171,14 @@ impl FactoryComponent for ResultRow {
                 },
             },
 
+            gtk::Button {
+                set_icon_name: "document-edit-symbolic",
+                add_css_class: "flat",
+                set_tooltip_text: Some("edit tags"),
+                set_visible: matches!(&self.item, ResultItem::Track(t) if !t.source_url.is_empty()),
+                connect_clicked => ResultRowMsg::Edit,
+            },
+
             gtk::Button {
                 set_icon_name: "go-next-symbolic",
                 add_css_class: "flat",
@@ -136,15 +186,26 @@ impl FactoryComponent for ResultRow {
                 #[watch]
                 set_visible: matches!(self.item,
                     ResultItem::Album(_) | ResultItem::Artist(_)
-                    | ResultItem::SpotifyPlaylist(_) | ResultItem::SpotifyLiked
+                    | ResultItem::SpotifyPlaylist(_) | ResultItem::SpotifyLiked | ResultItem::SpotifySection(_)
                 ),
                 connect_clicked => ResultRowMsg::Browse,
             },
         }
     }
 
-    fn init_model(item: Self::Init, _index: &DynamicIndex, _sender: FactorySender<Self>) -> Self {
-        Self { item, selected: false }
+    fn init_model(item: Self::Init, index: &DynamicIndex, sender: FactorySender<Self>) -> Self {
+        let url = item.thumb_url().to_string();
+        let thumb = thumbs::get(&url);
+        if thumb.is_none() && !url.is_empty() {
+            sender.oneshot_command(async move { covers::thumb(&url).await });
+        }
+        Self { item, selected: false, thumb, index: index.clone() }
+    }
+
+    fn update_cmd(&mut self, data: Self::CommandOutput, _sender: FactorySender<Self>) {
+        if let Some(data) = data {
+            self.thumb = thumbs::insert(self.item.thumb_url(), data);
+        }
     }
 
     fn update(&mut self, msg: Self::Input, sender: FactorySender<Self>) {
@@ -161,8 +222,10 @@ impl FactoryComponent for ResultRow {
                     let _ = sender.output(ResultRowOutput::SpotifyPlaylist(p.id.clone(), p.name.clone()));
                 }
                 ResultItem::SpotifyLiked => { let _ = sender.output(ResultRowOutput::SpotifyLiked); }
+                ResultItem::SpotifySection(sec) => { let _ = sender.output(ResultRowOutput::SpotifySection(*sec)); }
                 _ => {}
             },
+            ResultRowMsg::Edit => { let _ = sender.output(ResultRowOutput::Edit(self.index.clone())); }
         }
     }
 }
@@ -1,15 +1,42 @@
 use relm4::prelude::*;
 
 use crate::backend;
+use crate::backend::links::{Kind, Share};
+use crate::backend::spotify;
+use crate::models::{Album, Artist, Track};
 use super::app::{App, Msg};
+use super::dialogs;
 use super::result_row::ResultItem;
+use super::sp::{in_playlist, lookup_tokens};
 
 pub fn search(app: &mut App, query: String, sender: ComponentSender<App>) {
     app.searching = true;
     app.busy = true;
-    app.status = format!("searching \"{query}\"");
     app.results.guard().clear();
+    if let Some(link) = backend::links::share(&query) {
+        app.status = String::from("opening link");
+        let tokens = lookup_tokens(app);
+        let s = sender.input_sender().clone();
+        relm4::spawn(async move {
+            s.emit(Msg::SearchRes(open_share(link, tokens).await));
+        });
+        return;
+    }
+    if backend::links::is_url(&query) {
+        app.status = String::from("fetching link");
+        let s = sender.input_sender().clone();
+        relm4::spawn(async move {
+            let res = backend::links::fetch(&query).await;
+            s.emit(Msg::SearchRes(res.map(|t| t.into_iter().map(ResultItem::Track).collect())));
+        });
+        return;
+    }
+    app.status = format!("searching \"{query}\"");
     let selected = app.filter.selected();
+    if app.source.selected() == 1 {
+        search_spotify(app, query, selected, sender);
+        return;
+    }
     let s = sender.input_sender().clone();
     relm4::spawn(async move {
         let mut items = Vec::new();
@@ -36,6 +63,100 @@ pub fn search(app: &mut App, query: String, sender: ComponentSender<App>) {
     });
 }
 
+fn search_spotify(app: &mut App, query: String, selected: u32, sender: ComponentSender<App>) {
+    let Some(tokens) = lookup_tokens(app) else {
+        app.searching = false;
+        app.busy = false;
+        app.status = not_connected();
+        return;
+    };
+    let kinds = match selected {
+        1 => "album",
+        2 => "artist",
+        3 => "track",
+        _ => "album,artist,playlist,track",
+    };
+    let s = sender.input_sender().clone();
+    relm4::spawn(async move {
+        let res = spotify::search(&tokens, &query, kinds).await.map(|found| {
+            let mut items = Vec::new();
+            items.extend(found.albums.into_iter().take(10).map(ResultItem::Album));
+            items.extend(found.artists.into_iter().take(5).map(ResultItem::Artist));
+            items.extend(found.playlists.into_iter().take(5).map(ResultItem::SpotifyPlaylist));
+            items.extend(found.tracks.into_iter().map(ResultItem::Track));
+            items
+        });
+        s.emit(match res {
+            Ok(items) if items.is_empty() => Msg::SearchRes(Err(String::from("no results"))),
+            res => Msg::SearchRes(res.map_err(|e| format!("spotify err: {e}"))),
+        });
+    });
+}
+
+/// albums and playlists open straight to their tracks, artists to their albums
+async fn open_share(link: Share, tokens: Option<spotify::Tokens>) -> Result<Vec<ResultItem>, String> {
+    let link = match link {
+        Share::DeezerShort(url) => backend::links::resolve_short(&url).await?,
+        link => link,
+    };
+    let tracks: Vec<Track> = match link {
+        Share::Deezer(Kind::Track, id) => vec![backend::deezer::track(id).await?],
+        Share::Deezer(Kind::Album, id) => {
+            let album = backend::deezer::album_by_id(id).await?;
+            backend::deezer::album_tracks(&album).await?
+        }
+        Share::Deezer(Kind::Artist, id) => {
+            let artist = backend::deezer::artist(id).await?;
+            let albums = backend::deezer::artist_albums(&artist).await?;
+            return Ok(albums.into_iter().map(ResultItem::Album).collect());
+        }
+        Share::Deezer(Kind::Playlist, id) => {
+            let (name, tracks) = backend::deezer::playlist(id).await?;
+            in_playlist(tracks, &name)
+        }
+        Share::Spotify(kind, id) => {
+            let tokens = tokens.ok_or_else(not_connected)?;
+            match kind {
+                Kind::Track => vec![spotify::track(&tokens, &id).await?],
+                Kind::Album => spotify::album_tracks(&tokens, &id).await?,
+                Kind::Artist => {
+                    let albums = spotify::artist_albums(&tokens, &id).await?;
+                    return Ok(albums.into_iter().map(ResultItem::Album).collect());
+                }
+                Kind::Playlist => {
+                    let (name, tracks) = spotify::playlist(&tokens, &id).await?;
+                    in_playlist(tracks, &name)
+                }
+            }
+        }
+        Share::DeezerShort(_) => return Err(String::from("unrecognized deezer link")),
+    };
+    if tracks.is_empty() {
+        return Err(String::from("nothing behind that link"));
+    }
+    Ok(tracks.into_iter().map(ResultItem::Track).collect())
+}
+
+fn not_connected() -> String {
+    String::from("connect spotify or set a client secret first")
+}
+
+/// an artist's albums from whichever service the artist came from
+pub async fn fetch_artist_albums(artist: &Artist, tokens: Option<spotify::Tokens>) -> Result<Vec<Album>, String> {
+    if artist.sp_id.is_empty() {
+        return backend::deezer::artist_albums(artist).await;
+    }
+    spotify::artist_albums(&tokens.ok_or_else(not_connected)?, &artist.sp_id).await
+}
+
+/// an album's tracks from whichever service the album came from
+pub async fn fetch_album(album: &Album, tokens: Option<spotify::Tokens>) -> Result<Vec<Track>, String> {
+    if album.sp_id.is_empty() {
+        return backend::deezer::album_tracks(album).await;
+    }
+    spotify::album_tracks(&tokens.ok_or_else(not_connected)?, &album.sp_id).await
+}
+
 pub fn search_done(app: &mut App, items: Vec<ResultItem>) {
     app.searching = false;
     app.busy = false;
@@ -46,16 +167,17 @@ pub fn search_done(app: &mut App, items: Vec<ResultItem>) {
     }
 }
 
-pub fn browse_artist(app: &mut App, artist: crate::models::Artist, sender: ComponentSender<App>) {
+pub fn browse_artist(app: &mut App, artist: Artist, sender: ComponentSender<App>) {
     app.busy = true;
     app.status = format!("loading \"{}\"", artist.name);
+    let tokens = lookup_tokens(app);
     let s = sender.input_sender().clone();
     relm4::spawn(async move {
-        s.emit(Msg::ArtistAlbums(backend::deezer::artist_albums(&artist).await));
+        s.emit(Msg::ArtistAlbums(fetch_artist_albums(&artist, tokens).await));
     });
 }
 
-pub fn artist_albums(app: &mut App, albums: Vec<crate::models::Album>) {
+pub fn artist_albums(app: &mut App, albums: Vec<Album>) {
     app.busy = false;
     app.status = format!("{} albums", albums.len());
     let mut guard = app.results.guard();
@@ -65,12 +187,13 @@ pub fn artist_albums(app: &mut App, albums: Vec<crate::models::Album>) {
     }
 }
 
-pub fn browse_album(app: &mut App, album: crate::models::Album, sender: ComponentSender<App>) {
+pub fn browse_album(app: &mut App, album: Album, sender: ComponentSender<App>) {
     app.busy = true;
     app.status = format!("loading \"{}\"", album.title);
+    let tokens = lookup_tokens(app);
     let s = sender.input_sender().clone();
     relm4::spawn(async move {
-        s.emit(Msg::AlbumTracks(backend::deezer::album_tracks(&album).await));
+        s.emit(Msg::AlbumTracks(fetch_album(&album, tokens).await));
     });
 }
 
@@ -84,6 +207,22 @@ pub fn album_tracks(app: &mut App, tracks: Vec<crate::models::Track>) {
     }
 }
 
+/// metadata of a pasted link is whatever the uploader typed, let it be fixed before download
+pub fn edit_result(app: &mut App, index: DynamicIndex, root: &adw::ApplicationWindow, sender: ComponentSender<App>) {
+    let track = match app.results.guard().get(index.current_index()).map(|r| &r.item) {
+        Some(ResultItem::Track(t)) => t.clone(),
+        _ => return,
+    };
+    let s = sender.input_sender().clone();
+    dialogs::edit_track(root, &track, move |t| s.emit(Msg::ResultEdited(index.clone(), t)));
+}
+
+pub fn result_edited(app: &mut App, index: DynamicIndex, track: crate::models::Track) {
+    if let Some(row) = app.results.guard().get_mut(index.current_index()) {
+        row.item = ResultItem::Track(track);
+    }
+}
+
 pub fn select_all(app: &mut App, active: bool) {
     use adw::prelude::*;
     let len = app.results.guard().len();
@@ -14,15 +14,51 @@ pub fn connect(app: &mut App, sender: ComponentSender<App>) {
             return;
         }
     };
+    let port = app.settings.sp_port;
     app.busy = true;
     app.status = String::from("waiting for spotify auth...");
     let s = sender.input_sender().clone();
-    relm4::spawn(async move {
-        s.emit(Msg::SpAuth(spotify::authorize(&client_id).await));
-    });
+    if let Some(old) = app.sp_auth.take() {
+        old.abort();
+    }
+    app.sp_auth = Some(relm4::spawn(async move {
+        s.emit(Msg::SpAuth(spotify::authorize(&client_id, port).await));
+    }));
+}
+
+/// stop waiting for the browser, dropping the task frees the callback port
+pub fn cancel(app: &mut App) {
+    if let Some(task) = app.sp_auth.take() {
+        task.abort();
+    }
+    app.busy = false;
+    app.status = String::from("spotify login cancelled");
+    reset_connect(app);
+}
+
+pub fn auth_failed(app: &mut App, e: String) {
+    app.sp_auth = None;
+    app.busy = false;
+    app.status = format!("spotify err: {e}");
+    reset_connect(app);
+}
+
+/// settings rows back to the not connected state after a login that didn't finish
+fn reset_connect(app: &App) {
+    if let Some(row) = &app.sp_row {
+        row.set_subtitle("not connected");
+    }
+    if let Some(btn) = &app.sp_conn_btn {
+        btn.set_visible(true);
+        btn.set_sensitive(true);
+    }
+    if let Some(btn) = &app.sp_cancel_btn {
+        btn.set_visible(false);
+    }
 }
 
 pub fn auth_done(app: &mut App, tokens: spotify::Tokens, root: &adw::ApplicationWindow) {
+    app.sp_auth = None;
     app.busy = false;
     let name = tokens.display_name.clone();
     app.status = format!("connected as {name}");
@@ -37,6 +73,9 @@ pub fn auth_done(app: &mut App, tokens: spotify::Tokens, root: &adw::Application
     if let Some(btn) = &app.sp_disc_btn {
         btn.set_visible(true);
     }
+    if let Some(btn) = &app.sp_cancel_btn {
+        btn.set_visible(false);
+    }
     dialogs::show_popup(root, "object-select-symbolic", "Account Connected", &format!("signed in as {name}"));
 }
 
@@ -77,24 +116,40 @@ pub fn load_library(app: &mut App, sender: ComponentSender<App>) {
 pub fn library_loaded(app: &mut App, playlists: Vec<spotify::Playlist>) {
     app.busy = false;
     app.status = format!("{} playlists", playlists.len() + 1);
+    if app.sp_tokens.as_ref().is_some_and(|t| !spotify::missing_scopes(t).is_empty()) {
+        app.status.push_str(", reconnect spotify to load every section");
+    }
     let mut guard = app.results.guard();
     guard.clear();
     guard.push_back(ResultItem::SpotifyLiked);
+    guard.push_back(ResultItem::SpotifySection(spotify::Section::SavedAlbums));
+    guard.push_back(ResultItem::SpotifySection(spotify::Section::FollowedArtists));
+    for term in spotify::Term::ALL {
+        guard.push_back(ResultItem::SpotifySection(spotify::Section::Top(term)));
+    }
+    guard.push_back(ResultItem::SpotifySection(spotify::Section::Recent));
     for p in playlists {
         guard.push_back(ResultItem::SpotifyPlaylist(p));
     }
 }
 
+/// the user's tokens, or app-only ones for public lookups when a client secret is set
+pub(super) fn lookup_tokens(app: &App) -> Option<spotify::Tokens> {
+    app.sp_tokens.clone().or_else(spotify::public_tokens)
+}
+
 pub fn load_playlist(app: &mut App, id: String, name: String, sender: ComponentSender<App>) {
-    let tokens = match &app.sp_tokens {
-        Some(t) => t.clone(),
-        None => return,
+    let Some(tokens) = lookup_tokens(app) else {
+        app.status = String::from("connect spotify or set a client secret first");
+        return;
     };
     app.busy = true;
     app.status = format!("loading \"{name}\"");
     let s = sender.input_sender().clone();
     relm4::spawn(async move {
-        s.emit(Msg::SpTracks(spotify::playlist_tracks(&tokens, &id).await));
+        let res = spotify::playlist_tracks(&tokens, &id).await
+            .map(|tracks| in_playlist(tracks, &name));
+        s.emit(Msg::SpTracks(res));
     });
 }
 
@@ -107,10 +162,42 @@ pub fn load_liked(app: &mut App, sender: ComponentSender<App>) {
     app.status = String::from("loading liked songs...");
     let s = sender.input_sender().clone();
     relm4::spawn(async move {
-        s.emit(Msg::SpTracks(spotify::liked_tracks(&tokens).await));
+        let res = spotify::liked_tracks(&tokens).await
+            .map(|tracks| in_playlist(tracks, "Liked Songs"));
+        s.emit(Msg::SpTracks(res));
+    });
+}
+
+pub fn load_section(app: &mut App, section: spotify::Section, sender: ComponentSender<App>) {
+    let tokens = match &app.sp_tokens {
+        Some(t) => t.clone(),
+        None => return,
+    };
+    if !tokens.has_scope(section.scope()) {
+        app.status = format!("{} needs a new spotify login, disconnect and connect again", section.label().to_lowercase());
+        return;
+    }
+    app.busy = true;
+    app.status = format!("loading {}...", section.label().to_lowercase());
+    let s = sender.input_sender().clone();
+    relm4::spawn(async move {
+        // top and recent tracks get a playlist name so they land in their own folder
+        let msg = match section {
+            spotify::Section::SavedAlbums => Msg::ArtistAlbums(spotify::saved_albums(&tokens).await),
+            spotify::Section::FollowedArtists => Msg::SpArtists(spotify::followed_artists(&tokens).await),
+            spotify::Section::Top(term) => Msg::SpTracks(spotify::top_tracks(&tokens, term).await
+                .map(|tracks| in_playlist(tracks, section.label()))),
+            spotify::Section::Recent => Msg::SpTracks(spotify::recently_played(&tokens).await
+                .map(|tracks| in_playlist(tracks, section.label()))),
+        };
+        s.emit(msg);
     });
 }
 
+pub(super) fn in_playlist(tracks: Vec<crate::models::Track>, name: &str) -> Vec<crate::models::Track> {
+    tracks.into_iter().map(|mut t| { t.playlist = name.to_string(); t }).collect()
+}
+
 pub fn tracks_loaded(app: &mut App, tracks: Vec<crate::models::Track>) {
     app.busy = false;
     app.status = format!("{} tracks", tracks.len());
@@ -120,3 +207,13 @@ pub fn tracks_loaded(app: &mut App, tracks: Vec<crate::models::Track>) {
         guard.push_back(ResultItem::Track(t));
     }
 }
+
+pub fn artists_loaded(app: &mut App, artists: Vec<crate::models::Artist>) {
+    app.busy = false;
+    app.status = format!("{} artists", artists.len());
+    let mut guard = app.results.guard();
+    guard.clear();
+    for a in artists {
+        guard.push_back(ResultItem::Artist(a));
+    }
+}
@@ -0,0 +1,37 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+
+use gtk::{gdk, glib};
+
+/// decoded thumbnails kept in memory, the disk cache holds the rest
+const MAX_TEXTURES: usize = 300;
+
+#[derive(Default)]
+struct Textures {
+    map: HashMap<String, gdk::Texture>,
+    order: VecDeque<String>,
+}
+
+thread_local! {
+    static TEXTURES: RefCell<Textures> = RefCell::default();
+}
+
+pub fn get(url: &str) -> Option<gdk::Texture> {
+    TEXTURES.with_borrow(|t| t.map.get(url).cloned())
+}
+
+/// decode and remember, the oldest texture goes once the cache is full
+pub fn insert(url: &str, data: Vec<u8>) -> Option<gdk::Texture> {
+    let tex = gdk::Texture::from_bytes(&glib::Bytes::from_owned(data)).ok()?;
+    TEXTURES.with_borrow_mut(|t| {
+        if t.map.insert(url.to_string(), tex.clone()).is_none() {
+            t.order.push_back(url.to_string());
+        }
+        while t.order.len() > MAX_TEXTURES {
+            if let Some(old) = t.order.pop_front() {
+                t.map.remove(&old);
+            }
+        }
+    });
+    Some(tex)
+}